@@ -1,23 +1,28 @@
 use std::collections::HashMap;
 
-use crate::{prelude::*, undo::UndoStack};
+use crate::prelude::*;
 
 use fontdue::{ Font, Metrics };
-
-#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
-pub enum CodeEditorMode {
-    Rhai,
-    Text,
-    Settings,
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Where scroll_to_line() places the line in the viewport
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum ScrollAlign {
+    Top,
+    Center,
+    Bottom,
+    /// Only scrolls if the line is not visible, keeping the scroll margin
+    Nearest,
 }
 
 pub struct CodeEditor {
 
-    font                    : Option<Font>,
+    /// The font family, regular, bold, italic and bold italic
+    fonts                   : [Option<Font>; 4],
     draw2d                  : Draw2D,
 
     rect                    : (usize, usize, usize, usize),
-    text                    : String,
+    text                    : TextBuffer,
 
     pub font_size           : f32,
 
@@ -25,16 +30,24 @@ pub struct CodeEditor {
     pub cursor_pos          : (usize, usize),
     pub cursor_rect         : (usize, usize, usize, usize),
 
+    /// The display column vertical movement tries to keep, for every selection
+    desired_columns         : Vec<Option<usize>>,
+
     needs_update            : bool,
     pub mode                : CodeEditorMode,
+    languages               : LanguageRegistry,
 
-    line_numbers_buffer     : Vec<u8>,
-    line_numbers_size       : (usize, usize),
+    line_cache              : Vec<Option<Vec<u8>>>,
+    /// The range of lines which may have a bitmap in the line cache
+    cached_lines            : (usize, usize),
+    /// The width of every line in columns, None if the line changed since the last update
+    line_columns            : Vec<Option<usize>>,
+    text_size               : (usize, usize),
 
-    text_buffer             : Vec<u8>,
-    text_buffer_size        : (usize, usize),
+    highlighter             : Highlighter,
 
-    metrics                 : HashMap<char, (Metrics, Vec<u8>)>,
+    /// The rasterized glyphs by char and font index
+    metrics                 : HashMap<(char, usize), (Metrics, Vec<u8>)>,
     advance_width           : usize,
     advance_height          : usize,
 
@@ -52,11 +65,21 @@ pub struct CodeEditor {
     offset                  : (isize, isize),
     max_offset              : (usize, usize),
 
-    range_buffer            : (usize, usize),
-    range_start             : Option<(usize, usize)>,
-    range_end               : Option<(usize, usize)>,
+    selections              : SelectionSet,
+
+    /// The anchor and head (display column, line) of the block selection, columns may be past the
+    /// line end
+    block                   : Option<((usize, usize), (usize, usize))>,
+    /// The anchor of a block selection drag
+    block_drag              : Option<(usize, usize)>,
+    /// The text last copied from a block selection, pasted as a block
+    block_clipboard         : Option<String>,
+
+    /// The active search, its matches are highlighted
+    search                  : Option<Search>,
+    /// The open find bar, it receives the key input
+    find_bar                : Option<FindBar>,
 
-    last_pos                : (usize, usize),
     last_click              : u128,
     click_stage             : i32,
 
@@ -65,6 +88,8 @@ pub struct CodeEditor {
     pub drag_pos            : Option<(usize, usize)>,
 
     undo_stack              : UndoStack,
+    /// The edits which are not in the undo stack yet
+    edits                   : Vec<Edit>,
 }
 
 impl CodeEditor {
@@ -72,11 +97,11 @@ impl CodeEditor {
     pub fn new() -> Self where Self: Sized {
 
         Self {
-            font                        : None,
+            fonts                       : [None, None, None, None],
             draw2d                      : Draw2D {},
 
             rect                        : (0, 0, 0, 0),
-            text                        : "".to_string(),
+            text                        : TextBuffer::new(),
 
             font_size                   : 17.0,
 
@@ -84,14 +109,18 @@ impl CodeEditor {
             cursor_pos                  : (0, 0),
             cursor_rect                 : (0, 0, 2, 0),
 
+            desired_columns             : vec![],
+
             needs_update                : true,
             mode                        : CodeEditorMode::Rhai,
+            languages                   : LanguageRegistry::new(),
 
-            line_numbers_buffer         : vec![0;1],
-            line_numbers_size           : (0, 0),
+            line_cache                  : vec![None],
+            cached_lines                : (0, 0),
+            line_columns                : vec![Some(0)],
+            text_size                   : (0, 0),
 
-            text_buffer                 : vec![0;1],
-            text_buffer_size            : (0, 0),
+            highlighter                 : Highlighter::new(),
 
             metrics                     : HashMap::new(),
             advance_width               : 10,
@@ -111,11 +140,15 @@ impl CodeEditor {
             offset                      : (0, 0),
             max_offset                  : (0, 0),
 
-            range_buffer                : (0, 0),
-            range_start                 : None,
-            range_end                   : None,
+            selections                  : SelectionSet::default(),
+
+            block                       : None,
+            block_drag                  : None,
+            block_clipboard             : None,
+
+            search                      : None,
+            find_bar                    : None,
 
-            last_pos                    : (0, 0),
             last_click                  : 0,
             click_stage                 : 0,
 
@@ -124,50 +157,111 @@ impl CodeEditor {
             drag_pos                    : None,
 
             undo_stack                  : UndoStack::new(),
+            edits                       : vec![],
         }
     }
 
     /// Sets the path to the font file
     pub fn set_font(&mut self, path: &str) {
 
-        if let Some(font_bytes) = std::fs::read(path).ok() {
-            if let Some(font) = Font::from_bytes(font_bytes, fontdue::FontSettings::default()).ok() {
-                self.font = Some(font);
-            }
+        if let Some(font) = Self::load_font(path) {
+            self.set_font_data(font);
         }
     }
 
     /// Sets the font directly
     pub fn set_font_data(&mut self, font: Font) {
-        self.font = Some(font);
+        self.fonts[0] = Some(font);
+        self.metrics.clear();
+        self.reset_line_cache();
+    }
+
+    /// Sets the paths to the font files of a font family. Styles without a font use the regular font.
+    pub fn set_font_family(&mut self, regular: &str, bold: &str, italic: &str, bold_italic: &str) {
+        self.set_font(regular);
+        self.fonts[Self::font_index(true, false)] = Self::load_font(bold);
+        self.fonts[Self::font_index(false, true)] = Self::load_font(italic);
+        self.fonts[Self::font_index(true, true)] = Self::load_font(bold_italic);
+        self.metrics.clear();
+        self.reset_line_cache();
+    }
+
+    /// Sets the font of a style directly, None falls back to the regular font
+    pub fn set_styled_font_data(&mut self, bold: bool, italic: bool, font: Option<Font>) {
+        self.fonts[Self::font_index(bold, italic)] = font;
+        self.metrics.clear();
+        self.reset_line_cache();
+    }
+
+    fn load_font(path: &str) -> Option<Font> {
+        let font_bytes = std::fs::read(path).ok()?;
+        Font::from_bytes(font_bytes, fontdue::FontSettings::default()).ok()
+    }
+
+    /// The index of the font of the given style in the font family
+    fn font_index(bold: bool, italic: bool) -> usize {
+        bold as usize + italic as usize * 2
     }
 
     /// Sets the font size
     pub fn set_font_size(&mut self, font_size: f32) {
 
-        if let Some(font) = &self.font {
+        if let Some(font) = &self.fonts[0] {
             let m = font.rasterize('w', font_size);
             self.advance_width = m.0.advance_width as usize;
             self.advance_height = (font_size + 4.0) as usize;
             self.font_size = font_size;
+            self.metrics.clear();
+            self.reset_line_cache();
         }
     }
 
     /// Set the text / code to be edited
     pub fn set_text(&mut self, text: String) {
-        self.text = text;
-        self.needs_update = true;
+        self.text = TextBuffer::from(text.as_str());
+        if self.settings.detect_indentation {
+            match self.text.detect_indentation() {
+                Some(Indentation::Tabs) => self.settings.insert_spaces = false,
+                Some(Indentation::Spaces(width)) => {
+                    self.settings.insert_spaces = true;
+                    self.settings.tab_width = width;
+                },
+                None => {}
+            }
+        }
+        self.reset_line_cache();
         self.set_cursor((0, 0));
-        self.undo_stack = UndoStack::new();
+        self.undo_stack.clear();
+        self.edits.clear();
     }
 
     /// Returns the edited text
     pub fn get_text(&mut self) -> String {
-        self.text.clone()
+        self.text.to_string()
+    }
+
+    /// The lines which have a rendered bitmap in the line cache
+    pub fn get_cached_lines(&self) -> Vec<usize> {
+        (0..self.line_cache.len()).filter(|line| self.line_cache[*line].is_some()).collect()
+    }
+
+    /// Sets the distance of the tab stops and the width of one level of indentation
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.settings.tab_width = tab_width.max(1);
+        self.reset_line_cache();
+        self.update_cursor();
+    }
+
+    /// Loads a theme from a TOML, JSON or VS Code theme file
+    pub fn load_theme(&mut self, path: &str) -> Result<(), String> {
+        let theme = Theme::from_file(path)?;
+        self.set_theme(theme);
+        Ok(())
     }
 
     pub fn set_theme(&mut self, theme: Theme) {
         self.theme = theme;
+        self.reset_line_cache();
     }
 
     pub fn set_error(&mut self, error: Option<(String, Option<usize>)>) {
@@ -175,16 +269,61 @@ impl CodeEditor {
         self.needs_update = true;
     }
 
+    /// Returns the token spans of the given line, the byte offsets of the spans refer to get_line()
+    pub fn get_line_tokens(&mut self, line: usize) -> Vec<TokenSpan> {
+        self.update_highlighting(line);
+        self.highlighter.line_tokens(line).to_vec()
+    }
+
+    /// Returns the given line without its line ending
+    pub fn get_line(&self, line: usize) -> String {
+        self.text.line(line)
+    }
+
+    /// Registers a language and returns the mode to pass to set_mode(). A language with the same
+    /// name is replaced, this way the built-in languages can be customized.
+    pub fn register_language(&mut self, definition: LanguageDefinition) -> CodeEditorMode {
+        let mode = self.languages.register(definition);
+        if mode == self.mode {
+            self.reset_line_cache();
+        }
+        mode
+    }
+
+    /// Loads a language definition from a .toml or .json file and registers it
+    pub fn load_language(&mut self, path: &str) -> Result<CodeEditorMode, String> {
+        let definition = LanguageDefinition::from_file(path)?;
+        Ok(self.register_language(definition))
+    }
+
+    /// Returns the mode of the registered language with the given name
+    pub fn get_mode_by_name(&self, name: &str) -> Option<CodeEditorMode> {
+        self.languages.find(name)
+    }
+
+    /// Returns the mode of the registered language handling the given file extension
+    pub fn get_mode_by_extension(&self, extension: &str) -> Option<CodeEditorMode> {
+        self.languages.find_by_extension(extension)
+    }
+
+    /// Returns the definition of the current language
+    pub fn get_language(&self) -> &LanguageDefinition {
+        &self.languages.get(self.mode).definition
+    }
+
     /// Sets the mode of the editor
     pub fn set_mode(&mut self, mode: CodeEditorMode) {
         self.offset = (0, 0);
         self.mode = mode;
+        self.reset_line_cache();
     }
 
     pub fn draw(&mut self, frame: &mut [u8], rect: (usize, usize, usize, usize), stride: usize) {
 
         if self.needs_update {
             self.process_text();
+            // The text may be shorter now than the scroll offset
+            self.offset_sanity_check();
         }
 
         if let Some(drag_pos) = self.drag_pos {
@@ -206,580 +345,1338 @@ impl CodeEditor {
 
         self.rect = rect.clone();
 
+        let left_size = self.settings.line_number_width;
+
         self.draw2d.draw_rect(frame, &rect, stride, &self.theme.background);
         self.draw2d.draw_rect(frame, &(rect.0, rect.1, 95, rect.3), stride, &self.theme.line_numbers_bg);
+        self.draw2d.draw_rect(frame, &(rect.0 + 95, rect.1, 1, rect.3), stride, &self.theme.gutter_separator);
 
-        let x = self.line_numbers_size.0 as isize + rect.0 as isize - self.offset.0 * self.advance_width as isize;
-        let y = rect.1 as isize - self.offset.1 * self.advance_height as isize;
-
-        // Line Numbers
-        self.draw2d.blend_slice_safe(frame, &mut &self.line_numbers_buffer[..], &(0, y, self.line_numbers_size.0, self.line_numbers_size.1), stride, &rect);
-
-        // Code
-        let code_safe_rect = (rect.0 + self.line_numbers_size.0, rect.1, rect.2 - self.line_numbers_size.0, rect.3);
-        self.draw2d.blend_slice_safe(frame, &mut self.text_buffer[..], &(x, y, self.text_buffer_size.0, self.text_buffer_size.1), stride, &code_safe_rect);
+        let code_safe_rect = (rect.0 + left_size, rect.1, rect.2 - left_size, rect.3);
         self.code_safe_rect = code_safe_rect;
 
-        // Cursor
-        self.draw2d.draw_rect_safe(frame, &((rect.0 + self.line_numbers_size.0 + self.cursor_rect.0) as isize - self.offset.0 * self.advance_width as isize, (rect.1 + self.cursor_rect.1) as isize - self.offset.1 * self.advance_height as isize, self.cursor_rect.2, self.cursor_rect.3), stride, &self.theme.cursor, &code_safe_rect);
-    }
+        let x = (rect.0 + left_size) as isize - self.offset.0 * self.advance_width as isize;
 
-    // Inside the selection range ?
-    fn inside_selection(&self, x: usize, y: usize) -> bool {
-        let mut inside = false;
+        // Only the lines inside the viewport are drawn
+        let first_line = self.offset.1.max(0) as usize;
+        let last_line = (first_line + rect.3 / self.advance_height + 1).min(self.text.len_lines());
 
-        if let Some(range_start) = self.range_start {
-            if let Some(range_end) = self.range_end {
-                if y > range_start.1 && y < range_end.1 || (y == range_start.1 && x >= range_start.0 && y != range_end.1) || (y == range_end.1 && x <= range_end.0 && y != range_start.1) || (y == range_start.1 && y == range_end.1 && x >= range_start.0 && x <= range_end.0) {
-                    inside = true;
-                }
+        // Only the visible lines and a margin around them keep their bitmaps
+        let margin = last_line.saturating_sub(first_line);
+        let keep = (first_line.saturating_sub(margin), (last_line + margin).min(self.line_cache.len()));
+        for line in self.cached_lines.0..self.cached_lines.1.min(self.line_cache.len()) {
+            if line < keep.0 || line >= keep.1 {
+                self.line_cache[line] = None;
             }
         }
+        self.cached_lines = keep;
 
-        inside
-    }
+        self.update_highlighting(last_line);
 
-    /// Takes the current text and renders it to the text_buffer bitmap
-    fn process_text(&mut self) {
+        let matches = if let Some(search) = &mut self.search { search.matches(&self.text).clone() } else { vec![] };
 
-        if let Some(font) = &self.font {
+        for line in first_line..last_line {
+            let y = (rect.1 + (line - first_line) * self.advance_height) as isize;
 
-            let mut lines = self.text.lines();
+            // Line Numbers
+            let mut number_color = self.theme.line_numbers;
+            if let Some(error) = &self.error {
+                if let Some(error_line) = error.1 {
+                    if error_line == line + 1 {
+                        number_color = self.theme.error;
+                    }
+                }
+            }
+            let number = format!("{}", line + 1);
+            let number_x = (rect.0 + left_size) as isize - 20 - (number.len() * self.advance_width) as isize;
+            self.blend_text(frame, stride, (number_x, y), number.as_str(), &number_color, &rect);
 
-            let mut screen_width = 0_usize;
-            let mut screen_height = 0_usize;
+            // Current line
+            if line == self.cursor_pos.1 {
+                self.draw2d.blend_rect_safe(frame, &(code_safe_rect.0 as isize, y, code_safe_rect.2, self.advance_height), stride, &self.theme.current_line, &code_safe_rect);
+            }
 
-            let mut y = 0;
+            // Search matches
+            let line_start = self.text.line_to_char(line);
+            let line_end = line_start + self.text.line(line).chars().count();
+            for m in matches[matches.partition_point(|m| m.1 <= line_start)..].iter().take_while(|m| m.0 <= line_end) {
+                self.draw_line_range(frame, stride, line, (x, y), &code_safe_rect, *m, &self.theme.search_match);
+            }
 
-            while let Some(line) = lines.next() {
-                let mut x = 0;
+            // Selection
+            self.draw_line_selection(frame, stride, line, (x, y), &code_safe_rect);
 
-                let mut chars = line.chars();
-                let mut line_width = 0;
-                while let Some(c) = chars.next() {
-                    if self.metrics.contains_key(&c) == false {
-                        let m= font.rasterize(c, self.font_size);
-                        self.metrics.insert(c, m);
-                    }
+            // Code
+            self.render_line(line);
+            if let Some(Some(bitmap)) = self.line_cache.get(line) {
+                let width = bitmap.len() / 4 / self.advance_height;
+                self.draw2d.blend_slice_safe(frame, &bitmap[..], &(x, y, width, self.advance_height), stride, &code_safe_rect);
+            }
+        }
 
-                    if let Some((metrics, _bitmap)) = self.metrics.get(&c) {
-                        line_width += metrics.advance_width.ceil() as usize;
-                        x += 1;
-                    }
-                }
+        // Cursors
+        for selection in self.selections.iter() {
+            let caret = self.caret_rect(self.text.char_to_pos(selection.head));
+            self.draw2d.draw_rect_safe(frame, &((rect.0 + left_size + caret.0) as isize - self.offset.0 * self.advance_width as isize, (rect.1 + caret.1) as isize - self.offset.1 * self.advance_height as isize, caret.2, caret.3), stride, &self.theme.cursor, &code_safe_rect);
+        }
 
-                if line_width > screen_width {
-                    screen_width = line_width;
-                }
+        self.draw_find_bar(frame, stride, &rect);
+    }
 
-                screen_height += self.advance_height;
-                self.last_pos = (x, y);
-                y += 1;
-            }
+    /// Draws the find bar with the query and the match count at the top right of the rect
+    fn draw_find_bar(&mut self, frame: &mut [u8], stride: usize, rect: &(usize, usize, usize, usize)) {
+        if self.find_bar.is_none() {
+            return;
+        }
+
+        let matches = self.find_all();
+        let selection = self.selections.primary();
 
-            //println!("{} x {}", screen_width, screen_height);
+        let width = (self.advance_width * 40).min(rect.2.saturating_sub(self.settings.line_number_width + 20));
+        let height = self.advance_height + 12;
 
-            self.max_offset.0 = screen_width / self.advance_width;
+        // The text is not clipped, so the bar is only drawn if it fits
+        if width < self.advance_width * 16 || rect.3 < height + 8 {
+            return;
+        }
+
+        let bar_rect = (rect.0 + rect.2 - width - 10, rect.1 + 4, width, height);
+        let background = self.theme.line_numbers_bg;
+        self.draw2d.draw_rounded_rect_with_border(frame, &bar_rect, stride, &(width as f64, height as f64), &background, &(6.0, 6.0, 6.0, 6.0), &self.theme.selection, 1.5);
+
+        if let (Some(bar), Some(font)) = (&self.find_bar, &self.fonts[0]) {
+            let mut status = if bar.query.is_empty() {
+                String::new()
+            } else if matches.is_empty() {
+                "No results".to_string()
+            } else if let Some(index) = matches.iter().position(|m| *m == (selection.start(), selection.end())) {
+                format!("{} of {}", index + 1, matches.len())
+            } else {
+                format!("{} results", matches.len())
+            };
 
-            let left_size = self.settings.line_number_width;
-            screen_height += left_size;
-            self.needs_update = false;
+            // Drop the status if it does not fit and show the end of a query which is too long
+            let mut columns = width.saturating_sub(30) / self.advance_width;
+            if status.chars().count() >= columns {
+                status.clear();
+            }
+            columns -= status.chars().count();
+            let skip = bar.query.chars().count().saturating_sub(columns);
+            let query : String = bar.query.chars().skip(skip).collect();
 
-            self.line_numbers_buffer = vec![0; left_size * screen_height * 4];
-            self.line_numbers_size = (left_size, screen_height);
+            let y = bar_rect.1 + 6;
+            self.draw2d.draw_text(frame, &(bar_rect.0 + 10, y), stride, font, self.font_size, query.as_str(), &self.theme.text, &background);
 
-            self.text_buffer = vec![0; screen_width * screen_height * 4];
-            self.text_buffer_size = (screen_width, screen_height);
+            let status_color = if matches.is_empty() { self.theme.error } else { self.theme.line_numbers };
+            let status_x = (bar_rect.0 + width - 10).saturating_sub(status.chars().count() * self.advance_width).max(bar_rect.0);
+            self.draw2d.draw_text(frame, &(status_x, y), stride, font, self.font_size, status.as_str(), &status_color, &background);
+        }
+    }
 
-            // Draw it
+    /// Blends the selections of the given line into the frame, pos is the screen position of the line
+    fn draw_line_selection(&self, frame: &mut [u8], stride: usize, line: usize, pos: (isize, isize), safe_rect: &(usize, usize, usize, usize)) {
+        for selection in self.selections.iter() {
+            if !selection.is_empty() {
+                self.draw_line_range(frame, stride, line, pos, safe_rect, (selection.start(), selection.end()), &self.theme.selection);
+            }
+        }
+    }
 
-            let mut scanner = Scanner::new(self.text.as_str());
+    /// Blends the part of the (start, end) char range on the given line into the frame
+    #[allow(clippy::too_many_arguments)]
+    fn draw_line_range(&self, frame: &mut [u8], stride: usize, line: usize, pos: (isize, isize), safe_rect: &(usize, usize, usize, usize), range: (usize, usize), color: &[u8;4]) {
+        let start = self.text.char_to_pos(range.0);
+        let end = self.text.char_to_pos(range.1);
 
-            let mut x = 0;
-            let mut y = 0;
+        if line < start.1 || line > end.1 {
+            return;
+        }
 
-            let stride = screen_width;
+        let line_len = self.text.line_len(line);
+        let from = if line == start.1 { start.0 } else { 0 };
+        let to = if line == end.1 { end.0 } else { line_len };
 
-            let mut line_number = 1;
+        if line_len == 0 {
+            // Draw empty selection marker
+            if line < end.1 {
+                self.draw2d.blend_rect_safe(frame, &(pos.0, pos.1, self.advance_width / 2, self.advance_height), stride, color, safe_rect);
+            }
+        } else if to > from {
+            let columns = self.text.display_columns(line, self.settings.tab_width);
+            let (from, to) = (columns[from.min(line_len)], columns[to.min(line_len)]);
+            let x = pos.0 + (from * self.advance_width) as isize;
+            self.draw2d.blend_rect_safe(frame, &(x, pos.1, (to - from) * self.advance_width, self.advance_height), stride, color, safe_rect);
+        }
+    }
 
-            let mut finished = false;
-            let mut color : [u8;4] = self.theme.text;
-            let mut number_printed_for_line = 0_usize;
+    /// Returns the color of the given token type
+    fn token_color(&self, kind: TokenType) -> [u8;4] {
+        match kind {
+            TokenType::Identifier => self.theme.identifier,
+            TokenType::Comment | TokenType::DocComment | TokenType::BlockComment => self.theme.comments,
+            TokenType::Number => self.theme.number,
+            TokenType::String | TokenType::MultilineString | TokenType::Quotation | TokenType::HexColor => self.theme.string,
+            TokenType::Keyword | TokenType::Builtin => self.theme.keywords,
+            TokenType::Bracket | TokenType::Interpolation => self.theme.brackets,
+            TokenType::Attribute => self.theme.attribute,
+            TokenType::Key => self.theme.key,
+            TokenType::Heading => self.theme.heading,
+            TokenType::Emphasis => self.theme.emphasis,
+            TokenType::Link => self.theme.link,
+            _ => self.theme.text,
+        }
+    }
 
-            let selection_color = [45, 133, 200, 255];//self.theme.keywords;
+    /// Returns the font style for the given token type
+    fn token_style(&self, kind: TokenType) -> FontStyle {
+        let styles = &self.theme.styles;
+        match kind {
+            TokenType::Identifier => styles.identifier,
+            TokenType::Comment | TokenType::DocComment | TokenType::BlockComment => styles.comments,
+            TokenType::Number => styles.number,
+            TokenType::String | TokenType::MultilineString | TokenType::Quotation | TokenType::HexColor => styles.string,
+            TokenType::Keyword | TokenType::Builtin => styles.keywords,
+            TokenType::Bracket | TokenType::Interpolation => styles.brackets,
+            TokenType::Attribute => styles.attribute,
+            TokenType::Key => styles.key,
+            TokenType::Heading => styles.heading,
+            TokenType::Emphasis => styles.emphasis,
+            TokenType::Link => styles.link,
+            _ => styles.text,
+        }
+    }
 
-            while finished == false {
+    /// Makes sure that the glyphs of all chars in the text are rasterized with the given font
+    fn rasterize_chars(&mut self, text: &str, font_index: usize) {
+        if let Some(font) = self.fonts[font_index].as_ref().or(self.fonts[0].as_ref()) {
+            for c in text.chars() {
+                if !self.metrics.contains_key(&(c, font_index)) {
+                    let m= font.rasterize(c, self.font_size);
+                    self.metrics.insert((c, font_index), m);
+                }
+            }
+        }
+    }
 
-                let token = scanner.scan_token();
-                let mut printit = false;
+    /// Blends the text into the frame on the monospace grid, clipped to the safe rect
+    fn blend_text(&mut self, frame: &mut [u8], stride: usize, pos: (isize, isize), text: &str, color: &[u8;4], safe_rect: &(usize, usize, usize, usize)) {
+        self.rasterize_chars(text, 0);
 
-                match token.kind {
+        let mut x = pos.0;
+        for grapheme in text.graphemes(true) {
+            for c in grapheme.chars() {
+                if let Some((metrics, bitmap)) = self.metrics.get(&(c, 0)) {
+                    for cy in 0..metrics.height {
+                        for cx in 0..metrics.width {
 
-                    TokenType::LineFeed => {
+                            let fx = x + cx as isize + metrics.xmin as isize;
+                            let fy = pos.1 + cy as isize + self.font_size as isize - metrics.height as isize - metrics.ymin as isize;
 
-                        let mut text_color = &self.theme.line_numbers;
-                        if let Some(error) = &self.error {
-                            if let Some(line) = error.1 {
-                                if line == line_number {
-                                    text_color = &self.theme.error;
-                                }
-                            }
-                        }
-                        self.draw2d.draw_text_rect(&mut self.line_numbers_buffer[..], &(0, y, left_size - 20, self.advance_height), left_size, font, self.font_size, format!("{}", line_number).as_str(), &text_color, &self.theme.background, crate::draw2d::TextAlignment::Right);
-                        number_printed_for_line = line_number;
-
-                        if x == 0 {
-                            // Draw empty selection marker ?
-                            if self.inside_selection(0,  y / self.advance_height) {
-                                let bcolor = [45, 133, 200, 255];//self.theme.keywords;
-                                self.draw2d.blend_rect(&mut self.text_buffer[..], &(x, y, self.advance_width / 2, self.advance_height), stride, &bcolor);
+                            if fx < safe_rect.0 as isize || fy < safe_rect.1 as isize || fx >= (safe_rect.0 + safe_rect.2) as isize || fy >= (safe_rect.1 + safe_rect.3) as isize {
+                                continue;
                             }
-                        }
-                        x = 0;
-                        y += self.advance_height;
-                        line_number += 1;
-                    },
-                    TokenType::Space => {
-
-                        // Inside the selection range ?
-                        if self.inside_selection(x / self.advance_width,  y / self.advance_height) {
-                            let bcolor = [45, 133, 200, 255];//self.theme.keywords;
-                            self.draw2d.blend_rect(&mut self.text_buffer[..], &(x, y, self.advance_width, self.advance_height), stride, &bcolor);
-                        }
 
-                        x += self.advance_width
-                    },
-                    TokenType::Eof => {
-
-                        if number_printed_for_line != line_number {
-                            let mut text_color = &self.theme.line_numbers;
-                            if let Some(error) = &self.error {
-                                if let Some(line) = error.1 {
-                                    if line == line_number {
-                                        text_color = &self.theme.error;
-                                    }
-                                }
-                            }
-                            self.draw2d.draw_text_rect(&mut self.line_numbers_buffer[..], &(0, y, left_size - 20, self.advance_height), left_size, font, self.font_size, format!("{}", line_number).as_str(), &text_color, &self.theme.background, crate::draw2d::TextAlignment::Right);
-                        }
+                            let i = fx as usize * 4 + fy as usize * stride * 4;
+                            let m = bitmap[cx + cy * metrics.width];
 
-                        finished = true },
-
-                    TokenType::Identifier if self.mode == CodeEditorMode::Rhai || self.mode == CodeEditorMode::Settings => { color = self.theme.identifier; printit = true; },
-                    TokenType::SingeLineComment if self.mode == CodeEditorMode::Rhai || self.mode == CodeEditorMode::Settings => { color = self.theme.comments; printit = true; },
-                    TokenType::HexColor if self.mode == CodeEditorMode::Settings => { color = self.theme.string; printit = true; },
-                    TokenType::Number if self.mode == CodeEditorMode::Rhai || self.mode == CodeEditorMode::Settings => { color = self.theme.number; printit = true; },
-                    TokenType::String  if self.mode == CodeEditorMode::Rhai || self.mode == CodeEditorMode::Settings => { color = self.theme.string; printit = true; },
-                    TokenType::While if self.mode == CodeEditorMode::Rhai => { color = self.theme.keywords; printit = true; },
-                    TokenType::For if self.mode == CodeEditorMode::Rhai => { color = self.theme.keywords; printit = true; },
-                    TokenType::If if self.mode == CodeEditorMode::Rhai => { color = self.theme.keywords; printit = true; },
-                    TokenType::Else if self.mode == CodeEditorMode::Rhai => { color = self.theme.keywords; printit = true; },
-                    TokenType::Let if self.mode == CodeEditorMode::Rhai => { color = self.theme.keywords; printit = true; },
-                    TokenType::Fun if self.mode == CodeEditorMode::Rhai => { color = self.theme.keywords; printit = true; },
-                    TokenType::Print if self.mode == CodeEditorMode::Rhai => { color = self.theme.keywords; printit = true; },
-                    TokenType::Quotation if self.mode == CodeEditorMode::Rhai => { color = self.theme.string; printit = true; },
-
-                    TokenType::LeftBrace | TokenType::RightBrace | TokenType::LeftParen | TokenType::RightParen | TokenType::Dollar => { color = self.theme.brackets; printit = true; },
-
-                    _ => {
-                        color = self.theme.text;
-                        printit = true;
+                            let background = &[frame[i], frame[i+1], frame[i+2], frame[i+3]];
+                            frame[i..i + 4].copy_from_slice(&self.draw2d.mix_color(background, color, m as f64 / 255.0 * color[3] as f64 / 255.0));
+                        }
                     }
                 }
+            }
+            x += self.advance_width as isize;
+        }
+    }
 
-                // Print the current lexeme
-                if printit {
+    /// Renders the given line into the line cache if it is not cached yet. The line bitmap has a
+    /// transparent background so that selections can be drawn below it.
+    fn render_line(&mut self, line: usize) {
 
-                    let mut chars = token.lexeme.chars();
-                    while let Some(c) = chars.next() {
+        if line >= self.line_cache.len() || self.line_cache[line].is_some() {
+            return;
+        }
 
-                        if let Some((metrics, bitmap)) = self.metrics.get(&c) {
+        let text = self.text.line(line);
+        let show_whitespace = self.settings.show_whitespace;
 
-                            let mut bcolor = self.theme.background;
+        let tab_width = self.settings.tab_width.max(1);
+        let width = (self.text.display_columns(line, tab_width).last().copied().unwrap_or(0) + 1) * self.advance_width;
+        let height = self.advance_height;
+        let mut bitmap = vec![0; width * height * 4];
 
-                            // Inside the selection range ?
-                            if self.inside_selection(x / self.advance_width,  y / self.advance_height) {
-                                bcolor = selection_color;
-                                self.draw2d.blend_rect( &mut self.text_buffer[..], &(x, y, self.advance_width, self.advance_height), stride, &bcolor);
-                            }
+        let tokens = self.highlighter.line_tokens(line).to_vec();
+        let mut x = 0;
+
+        for token in tokens {
+            let color = self.token_color(token.kind);
+            let style = self.token_style(token.kind);
+            let font_index = Self::font_index(style.bold, style.italic);
+
+            self.rasterize_chars(&text[token.start..token.end], font_index);
+            if show_whitespace {
+                self.rasterize_chars("·→", font_index);
+            }
+
+            let token_x = x;
 
-                            let text_buffer_frame = &mut self.text_buffer[..];
-                            for cy in 0..metrics.height {
-                                for cx in 0..metrics.width {
+            for grapheme in text[token.start..token.end].graphemes(true) {
+                let tab = grapheme == "\t";
+                let (grapheme, color) = match grapheme {
+                    " " if show_whitespace => ("·", self.theme.whitespace),
+                    "\t" if show_whitespace => ("→", self.theme.whitespace),
+                    _ => (grapheme, color),
+                };
 
-                                    let fy = (self.font_size as isize - metrics.height as isize - metrics.ymin as isize) as usize;
+                // Combining marks are drawn on top of their base character
+                for c in grapheme.chars() {
+                    if let Some((metrics, glyph)) = self.metrics.get(&(c, font_index)) {
+                        for cy in 0..metrics.height {
+                            for cx in 0..metrics.width {
 
-                                    let i = (x + cx + metrics.xmin as usize) * 4 + (y + cy + fy) * stride * 4;
-                                    let m = bitmap[cx + cy * metrics.width];
+                                let fx = x as isize + cx as isize + metrics.xmin as isize;
+                                let fy = cy as isize + self.font_size as isize - metrics.height as isize - metrics.ymin as isize;
 
-                                    text_buffer_frame[i..i + 4].copy_from_slice(&self.draw2d.mix_color(&bcolor, &color, m as f64 / 255.0));
+                                if fx < 0 || fy < 0 || fx as usize >= width || fy as usize >= height {
+                                    continue;
+                                }
+
+                                let i = fx as usize * 4 + fy as usize * width * 4;
+                                let alpha = (glyph[cx + cy * metrics.width] as usize * color[3] as usize / 255) as u8;
+
+                                if alpha > bitmap[i + 3] {
+                                    bitmap[i..i + 4].copy_from_slice(&[color[0], color[1], color[2], alpha]);
                                 }
                             }
-                            x += self.advance_width;
                         }
                     }
                 }
+                // A tab advances to the next tab stop
+                x = if tab { (x / self.advance_width / tab_width + 1) * tab_width * self.advance_width } else { x + self.advance_width };
             }
 
-            self.max_offset.1 = line_number;
+            // Lines below the baseline and through the middle of the lower case letters
+            let thickness = (self.font_size / 14.0).max(1.0) as usize;
+            let mut rows = vec![];
+            if style.underline {
+                rows.push(self.font_size as usize + 2);
+            }
+            if style.strikethrough {
+                rows.push((self.font_size * 0.68) as usize);
+            }
+            for row in rows {
+                for y in row..(row + thickness).min(height) {
+                    for px in token_x..x.min(width) {
+                        let i = px * 4 + y * width * 4;
+                        bitmap[i..i + 4].copy_from_slice(&color);
+                    }
+                }
+            }
         }
+
+        self.line_cache[line] = Some(bitmap);
     }
 
-    /// Sets the cursor offset based on the given screen position
-    fn set_cursor_offset_from_pos(&mut self, pos: (usize, usize)) -> bool {
+    /// Scans the changed lines up to last_line, lines whose tokens changed need to be rendered again
+    fn update_highlighting(&mut self, last_line: usize) {
+        for line in self.highlighter.update(&self.text, self.languages.get(self.mode), last_line) {
+            if let Some(cached) = self.line_cache.get_mut(line) {
+                *cached = None;
+            }
+        }
+    }
 
-        let mut lines = self.text.lines();
+    /// Updates the line layout after the text changed and computes the scroll limits
+    fn process_text(&mut self) {
 
-        let px = pos.0;
-        let py = pos.1;
+        if self.line_cache.len() != self.text.len_lines() {
+            self.reset_line_cache();
+        }
 
-        let left_size = 0;
-        let line_height = self.advance_height;
+        for line in 0..self.line_columns.len() {
+            if self.line_columns[line].is_none() {
+                self.line_columns[line] = Some(self.line_width(line));
+            }
+        }
+        let max_columns = self.line_columns.iter().flatten().cloned().max().unwrap_or(0);
 
-        self.cursor_offset = 0;
+        self.max_offset.0 = max_columns;
+        self.max_offset.1 = self.text.len_lines();
 
-        let mut curr_line_index = 0_usize;
+        self.text_size = (max_columns * self.advance_width, self.text.len_lines() * self.advance_height + self.settings.line_number_width);
+        self.needs_update = false;
+    }
 
-        let mut y = 0;
+    /// Drops all cached lines, used when the whole text or its appearance changes
+    fn reset_line_cache(&mut self) {
+        let lines = self.text.len_lines();
+        self.line_cache = (0..lines).map(|_| None).collect();
+        self.cached_lines = (0, 0);
+        self.line_columns = vec![None; lines];
+        self.highlighter.reset(lines);
+        self.needs_update = true;
 
-        let mut found = false;
+        if let Some(search) = &mut self.search {
+            search.invalidate();
+        }
+    }
 
-        if self.text.is_empty() {
-            self.cursor_pos.0 = 0;
-            self.cursor_pos.1 = 0;
-            self.cursor_rect.0 = 0;
-            self.cursor_rect.1 = 0;
-            self.cursor_rect.3 = self.advance_height;
-            return true;
+    /// Invalidates the cache of the changed lines, old_count lines starting at line were replaced by new_count lines
+    fn invalidate_lines(&mut self, line: usize, old_count: usize, new_count: usize) {
+        if line + old_count > self.line_cache.len() {
+            self.reset_line_cache();
+            return;
+        }
+
+        // The widths are measured in process_text(), once for all edits since the last update
+        self.line_cache.splice(line..line + old_count, std::iter::repeat_with(|| None).take(new_count));
+        self.line_columns.splice(line..line + old_count, vec![None; new_count]);
+
+        // Cached lines after the change move by the difference of the line counts
+        if self.cached_lines.0 < self.cached_lines.1 {
+            self.cached_lines = (self.cached_lines.0.min(line), (self.cached_lines.1 + new_count).min(self.line_cache.len()));
         }
+        self.highlighter.lines_changed(line, old_count, new_count);
+        self.needs_update = true;
 
-        while let Some(line) = lines.next() {
+        if let Some(search) = &mut self.search {
+            search.invalidate();
+        }
+    }
 
-            if py >= y && py <= y + self.advance_height {
+    /// Inserts the text at the given char index
+    fn insert_text(&mut self, char_index: usize, text: &str) {
+        self.edits.push(Edit::new(char_index, "", text));
+        let line = self.text.char_to_line(char_index);
+        let new_lines = text.chars().filter(|c| *c == '\n').count();
+        self.text.insert(char_index, text);
+        self.invalidate_lines(line, 1, new_lines + 1);
+    }
 
-                self.cursor_pos.0 = 0;
-                self.cursor_pos.1 = curr_line_index;
-                self.cursor_rect.0 = 0;
-                self.cursor_rect.1 = y;
-                self.cursor_rect.3 = line_height;
+    /// Removes the text between the two char indices
+    fn remove_text(&mut self, start: usize, end: usize) {
+        self.edits.push(Edit::new(start, self.text.slice(start, end).as_str(), ""));
+        let line = self.text.char_to_line(start);
+        let removed_lines = self.text.char_to_line(end) - line;
+        self.text.remove(start, end);
+        self.invalidate_lines(line, removed_lines + 1, 1);
+    }
 
-                if px > left_size {
-                    self.cursor_pos.0 = (px - left_size) / self.advance_width + 1;
-                    if (px - left_size) % self.advance_width < self.advance_width /2 && self.cursor_pos.0 > 0 && self.cursor_pos.0 <= line.len() {
-                        self.cursor_pos.0 -= 1;
-                    }
-                    self.cursor_pos.0 = std::cmp::min(self.cursor_pos.0, line.len());
-                    if self.cursor_pos.0 > 0 {
-                        self.cursor_rect.0 += self.cursor_pos.0 * self.advance_width - 2;
-                    }
-                }
+    /// Converts the given position in the scrolled text area to a (display column, line) position
+    /// on the character grid, the column may be past the end of the line
+    fn screen_to_grid(&self, pos: (usize, usize)) -> (usize, usize) {
+        let line = (pos.1 / self.advance_height).min(self.text.len_lines() - 1);
+        let columns = self.text.display_columns(line, self.settings.tab_width);
+        let end = columns[columns.len() - 1];
 
-                self.cursor_offset += self.cursor_pos.0;
-                found = true;
+        if pos.0 > end * self.advance_width {
+            (end + (pos.0 - end * self.advance_width + self.advance_width / 2) / self.advance_width, line)
+        } else {
+            (columns[self.column_at_x(&columns, pos.0)], line)
+        }
+    }
 
-                break;
-            } else {
-                self.cursor_offset += line.len();
+    /// The column of the grapheme boundary closest to the x coordinate, for the display columns
+    /// of a line
+    fn column_at_x(&self, columns: &[usize], x: usize) -> usize {
+        for (column, pair) in columns.windows(2).enumerate() {
+            if x < (pair[0] + pair[1]) * self.advance_width / 2 {
+                return column;
             }
-
-            curr_line_index += 1;
-            y += line_height;
-            self.cursor_offset += 1;
         }
+        columns.len() - 1
+    }
 
-        // Check if there is an line feed at the end as this is cut off by lines()
-        if found == false {
-            if self.text.ends_with("\n") {
-                self.cursor_pos.0 = 0;
-                self.cursor_pos.1 = curr_line_index;
-                self.cursor_rect.0 = 0;
-                self.cursor_rect.1 = y;
-                self.cursor_rect.3 = line_height;
-            } else {
+    /// Converts the given position in the scrolled text area to a (column, line) position
+    fn screen_to_pos(&self, pos: (usize, usize)) -> (usize, usize) {
 
-                // Selection is out of scope, select the end of the text
+        let px = pos.0;
+        let py = pos.1;
 
-                let lines = self.text.lines();
-                if let Some(l) = lines.last() {
+        let line = py / self.advance_height;
 
-                    self.cursor_pos.0 = l.len();
-                    self.cursor_pos.1 = curr_line_index;
-                    self.cursor_rect.0 = l.len() * self.advance_width - 2;
-                    self.cursor_rect.1 = y - line_height;
-                    self.cursor_rect.3 = line_height;
-                }
-            }
+        if line >= self.text.len_lines() {
+            // Out of scope, use the end of the text
+            let last_line = self.text.len_lines() - 1;
+            return (self.text.line_len(last_line), last_line);
         }
 
-        true
+        let columns = self.text.display_columns(line, self.settings.tab_width);
+        (self.column_at_x(&columns, px), line)
     }
 
-    /// Sets the cursor to the given position
+    /// Sets the cursor to the given (column, line) position, clears the selection and scrolls the
+    /// cursor into view
     pub fn set_cursor(&mut self, pos: (usize, usize)) {
-        self.cursor_pos = pos;
-        self.cursor_rect.0 = pos.0 * self.advance_width;
-        self.cursor_rect.1 = (pos.1+1) * self.advance_height;
-        self.set_cursor_offset_from_pos((self.cursor_rect.0, self.cursor_rect.1));
+        self.move_cursor(pos, false);
+        self.scroll_to_cursor();
     }
 
-    /// Copies the given range and returns it
-    fn copy_range(&self, start: Option<(usize, usize)>, end: Option<(usize, usize)>) -> String {
-        let mut s = "".to_string();
-
-        let mut x = 0;
-        let mut y = 0;
-
-        let mut inside = false;
-
-        let mut chars = self.text.chars();
+    /// The amount of (columns, lines) visible in the code area
+    fn visible_size(&self) -> (usize, usize) {
+        ((self.code_safe_rect.2 / self.advance_width).max(1), (self.code_safe_rect.3 / self.advance_height).max(1))
+    }
 
-        while let Some(c) = chars.next() {
+    /// The scroll offset in (columns, lines)
+    pub fn get_scroll_offset(&self) -> (usize, usize) {
+        (self.offset.0.max(0) as usize, self.offset.1.max(0) as usize)
+    }
 
-            if inside == false {
-                if let Some(start) = start {
-                    if y > start.1 || (y == start.1 && x >= start.0)  {
-                        inside = true;
-                    }
+    /// Scrolls so that the given line is visible at the given alignment
+    pub fn scroll_to_line(&mut self, line: usize, align: ScrollAlign) {
+        let line = line.min(self.text.len_lines() - 1);
+        let visible = self.visible_size().1;
+
+        let first_line = match align {
+            ScrollAlign::Top => line,
+            ScrollAlign::Center => line.saturating_sub(visible / 2),
+            ScrollAlign::Bottom => (line + 1).saturating_sub(visible),
+            ScrollAlign::Nearest => {
+                let margin = self.settings.scroll_margin.1.min((visible - 1) / 2);
+                let first_line = self.offset.1.max(0) as usize;
+                if line < first_line + margin {
+                    line.saturating_sub(margin)
+                } else if line + margin >= first_line + visible {
+                    line + margin + 1 - visible
                 } else {
-                    inside = true;
+                    first_line
                 }
             }
+        };
 
-            if inside {
-                if let Some(end) = end {
-                    if y > end.1 || (y == end.1 && x >= end.0) {
-                        break;
-                    }
-                }
-            }
+        self.offset.1 = first_line as isize;
+        self.needs_update = true;
+    }
 
-            if inside {
-                s.push(c);
-            }
+    /// Scrolls the viewport so that the cursor stays visible, keeping the scroll margin
+    fn scroll_to_cursor(&mut self) {
+        // Not drawn yet, the size of the viewport is unknown
+        if self.code_safe_rect.3 == 0 {
+            return;
+        }
 
-            if c == '\n' {
-                x = 0;
-                y += 1;
-            } else {
-                x += 1;
-            }
+        self.scroll_to_line(self.cursor_pos.1, ScrollAlign::Nearest);
+
+        let visible = self.visible_size().0;
+        let margin = self.settings.scroll_margin.0.min((visible - 1) / 2);
+        let column = self.text.display_column(self.cursor_pos, self.settings.tab_width);
+        let first_column = self.offset.0.max(0) as usize;
+
+        if column < first_column + margin {
+            self.offset.0 = column.saturating_sub(margin) as isize;
+        } else if column + margin >= first_column + visible {
+            self.offset.0 = (column + margin + 1 - visible) as isize;
         }
-        s
     }
 
-    /// Copies the given range and returns it
-    fn copy_range_incl(&self, start: Option<(usize, usize)>, end: Option<(usize, usize)>) -> String {
-        let mut s = "".to_string();
+    /// Moves the cursor to the given (column, line) position. If extend is true the selection is
+    /// extended to the new position, otherwise the selection is cleared. Other cursors are removed.
+    fn move_cursor(&mut self, pos: (usize, usize), extend: bool) {
+        let line = pos.1.min(self.text.len_lines() - 1);
+        let column = pos.0.min(self.text.line_len(line));
 
-        let mut x = 0;
-        let mut y = 0;
+        let mut selection = self.selections.primary();
+        selection.move_head(self.text.pos_to_char((column, line)), extend);
 
-        let mut inside = false;
+        self.selections = SelectionSet::new(selection);
+        self.desired_columns.clear();
+        self.update_cursor();
+    }
 
-        let mut chars = self.text.chars();
+    /// Updates the cursor from the head of the primary selection after the selections changed, this
+    /// ends a block selection
+    fn update_cursor(&mut self) {
+        self.block = None;
+        self.cursor_offset = self.selections.primary().head;
+        self.cursor_pos = self.text.char_to_pos(self.cursor_offset);
+        self.cursor_rect = self.caret_rect(self.cursor_pos);
+        self.needs_update = true;
+    }
 
-        while let Some(c) = chars.next() {
+    /// The rect of the caret at the given (column, line) position, relative to the text origin
+    fn caret_rect(&self, pos: (usize, usize)) -> (usize, usize, usize, usize) {
+        let column = self.text.display_column(pos, self.settings.tab_width);
+        (if column > 0 { column * self.advance_width - 2 } else { 0 }, pos.1 * self.advance_height, self.cursor_rect.2, self.advance_height)
+    }
 
-            if inside == false {
-                if let Some(start) = start {
-                    if y > start.1 || (y == start.1 && x >= start.0)  {
-                        inside = true;
-                    }
-                } else {
-                    inside = true;
-                }
-            }
+    /// The width of the given line in columns, tabs extend to the next tab stop
+    fn line_width(&self, line: usize) -> usize {
+        self.text.display_columns(line, self.settings.tab_width).last().copied().unwrap_or(0)
+    }
 
-            if inside {
-                if let Some(end) = end {
-                    if y > end.1 || (y == end.1 && x > end.0) {
-                        break;
-                    }
-                }
-            }
+    /// Runs the movement for every cursor. The movement sees each cursor as the only one.
+    fn move_cursors<F: FnMut(&mut Self)>(&mut self, mut movement: F) {
+        let selections = self.selections.to_vec();
+        let primary = self.selections.primary_index();
+        let columns = std::mem::take(&mut self.desired_columns);
 
-            if inside {
-                s.push(c);
-            }
+        let mut moved = vec![];
+        let mut moved_columns = vec![];
 
-            if c == '\n' {
-                x = 0;
-                y += 1;
-            } else {
-                x += 1;
-            }
+        for (index, selection) in selections.into_iter().enumerate() {
+            self.selections = SelectionSet::new(selection);
+            self.desired_columns = vec![columns.get(index).copied().flatten()];
+            self.update_cursor();
+
+            movement(self);
+
+            moved.push(self.selections.primary());
+            moved_columns.push(self.desired_columns.first().copied().flatten());
         }
-        s
+
+        self.selections = SelectionSet::from_vec(moved, primary);
+        // Merged cursors lose their desired columns
+        self.desired_columns = if self.selections.len() == moved_columns.len() { moved_columns } else { vec![] };
+        self.update_cursor();
     }
 
-    pub fn key_down(&mut self, char: Option<char>, key: Option<WidgetKey>) -> bool {
+    /// Moves the cursor by the given amount of lines, keeping the desired display column
+    fn move_cursor_vertically(&mut self, lines: isize, extend: bool) {
+        let tab_width = self.settings.tab_width;
+        let column = self.desired_columns.first().copied().flatten().unwrap_or(self.text.display_column(self.cursor_pos, tab_width));
+        let line = self.cursor_pos.1 as isize + lines;
+
+        if line < 0 {
+            self.move_cursor((0, 0), extend);
+        } else if line as usize >= self.text.len_lines() {
+            let last_line = self.text.len_lines() - 1;
+            self.move_cursor((self.text.line_len(last_line), last_line), extend);
+        } else {
+            self.move_cursor((self.text.column_at_display(line as usize, column, tab_width), line as usize), extend);
+        }
+        self.desired_columns = vec![Some(column)];
+    }
 
-        let undo = self.text.clone();
-        let undo_pos = self.cursor_pos;
+    /// The amount of lines visible in the editor
+    fn page_lines(&self) -> usize {
+        (self.rect.3 / self.advance_height).max(1)
+    }
 
-        if self.logo || self.ctrl {
-            use copypasta::{ClipboardContext, ClipboardProvider};
+    /// The primary selection, its head is at the cursor
+    pub fn get_selection(&self) -> Selection {
+        self.selections.primary()
+    }
 
-            // Copy
-            if char == Some('c') || char == Some('C') {
-                let clip = self.copy_range_incl(self.range_start, self.range_end);
-                //println!("{}", clip);
+    /// All selections sorted by their position
+    pub fn get_selections(&self) -> Vec<Selection> {
+        self.selections.to_vec()
+    }
 
-                let mut ctx = ClipboardContext::new().unwrap();
-                _ = ctx.set_contents(clip.to_owned());
+    /// Selects the text between the anchor and the head (column, line) positions, the cursor is
+    /// placed at the head
+    pub fn set_selection(&mut self, anchor: (usize, usize), head: (usize, usize)) {
+        self.move_cursor(anchor, false);
+        self.move_cursor(head, true);
+    }
 
-                return true;
-            }
+    /// Adds a selection between the anchor and the head (column, line) positions and makes it the
+    /// primary selection, use the same position for both to add a cursor
+    pub fn add_selection(&mut self, anchor: (usize, usize), head: (usize, usize)) {
+        let anchor = self.text.pos_to_char(self.clamp_pos(anchor));
+        let head = self.text.pos_to_char(self.clamp_pos(head));
+        self.selections.add(Selection::new(anchor, head));
+        self.desired_columns.clear();
+        self.update_cursor();
+    }
 
-            // Cut
-            if char == Some('x') || char == Some('X') {
-                let clip = self.copy_range_incl(self.range_start, self.range_end);
+    /// Removes all selections but the primary one
+    pub fn clear_secondary_selections(&mut self) {
+        self.selections = SelectionSet::new(self.selections.primary());
+        self.desired_columns.clear();
+        self.update_cursor();
+    }
 
-                let mut ctx = ClipboardContext::new().unwrap();
-                _ = ctx.set_contents(clip.to_owned());
+    /// Clamps the (column, line) position to the text
+    fn clamp_pos(&self, pos: (usize, usize)) -> (usize, usize) {
+        let line = pos.1.min(self.text.len_lines() - 1);
+        (pos.0.min(self.text.line_len(line)), line)
+    }
 
-                if let Some(start) = self.range_start {
-                    if let Some(end) = self.range_end {
-                        let first_half = self.copy_range(None, Some((std::cmp::max(start.0, 0), start.1)));
-                        let second_half = self.copy_range(Some((end.0 + 1, end.1)), None);
-                        let text = first_half + second_half.as_str();
-                        self.text = text;
+    /// Selects the whole text
+    pub fn select_all(&mut self) {
+        let last_line = self.text.len_lines() - 1;
+        self.set_selection((0, 0), (self.text.line_len(last_line), last_line));
+    }
 
-                        self.range_start = None;
-                        self.range_end = None;
-                        self.process_text();
+    /// Returns the selected text, the text of multiple selections is joined by line breaks
+    pub fn get_selected_text(&self) -> String {
+        // A block keeps its empty lines
+        let block = self.block.is_some();
+        let texts : Vec<String> = self.selections.iter().filter(|s| block || !s.is_empty()).map(|s| self.text.slice(s.start(), s.end())).collect();
+        texts.join("\n")
+    }
 
-                        self.set_cursor((start.0, start.1));
-                    }
-                }
+    /// Replaces text at every selection. For each selection edit returns the char range to replace
+    /// and the new text, the cursor is placed after the new text. Returns false if nothing changed.
+    fn edit_selections<F>(&mut self, mut edit: F) -> bool where F: FnMut(&Self, usize, Selection) -> Option<(usize, usize, String)> {
+        self.edit_and_select(|editor, index, selection| {
+            edit(editor, index, selection).map(|(start, end, text)| {
+                let length = text.chars().count();
+                (start, end, text, Selection::cursor(length))
+            })
+        })
+    }
 
-                return true;
-            }
+    /// Like edit_selections, edit also returns the new selection in char offsets relative to the
+    /// start of the new text
+    fn edit_and_select<F>(&mut self, mut edit: F) -> bool where F: FnMut(&Self, usize, Selection) -> Option<(usize, usize, String, Selection)> {
+        let selections = self.selections.to_vec();
+        let primary = self.selections.primary_index();
 
-            // Paste
-            if char == Some('v') || char == Some('V') {
-                let mut ctx = ClipboardContext::new().unwrap();
-                if let Some(text) = ctx.get_contents().ok() {
-                    self.paste(text);
+        let mut edited = vec![];
+        // The change in length of the text by the previous edits
+        let mut delta : isize = 0;
+        let mut changed = false;
+
+        for (index, selection) in selections.into_iter().enumerate() {
+            let selection = Selection::new((selection.anchor as isize + delta) as usize, (selection.head as isize + delta) as usize);
+
+            if let Some((start, end, text, selected)) = edit(self, index, selection) {
+                if end > start {
+                    self.remove_text(start, end);
                 }
-                return true;
+                if !text.is_empty() {
+                    self.insert_text(start, text.as_str());
+                }
+                let length = text.chars().count();
+                delta += length as isize - (end - start) as isize;
+                edited.push(Selection::new(start + selected.anchor, start + selected.head));
+                changed = true;
+            } else {
+                edited.push(selection);
             }
         }
 
-        if let Some(key) = key {
-            match key {
-                WidgetKey::Delete => {
-                    let mut handled = false;
-                    if let Some(start) = self.range_start {
-                        if let Some(end) = self.range_end {
-                            let first_half = self.copy_range(None, Some((std::cmp::max(start.0, 0), start.1)));
-                            let second_half = self.copy_range(Some((end.0 + 1, end.1)), None);
-                            let text = first_half + second_half.as_str();
-                            self.text = text;
-                            self.range_start = None;
-                            self.range_end = None;
-                            self.process_text();
-                            handled = true;
-
-                            self.set_cursor(start);
-                        }
-                    }
-                    if handled == false && self.text.is_empty() == false && self.cursor_offset >= 1 {
-                        let mut number_of_chars_on_prev_line = 0_usize;
-                        let delete_line;
-                        if self.cursor_pos.0 == 0 {
-                            delete_line = true;
-                            if let Some(prev_line) = self.text.lines().nth(self.cursor_pos.1 - 1) {
-                                number_of_chars_on_prev_line = prev_line.len();
-                            }
-                        } else {
-                            delete_line = false;
-                        }
-
-                        let index = (self.cursor_offset - 1).clamp(0, self.text.len() - 1);
-                        _ = self.text.remove(index);
-                        self.process_text();
+        self.selections = SelectionSet::from_vec(edited, primary);
+        self.desired_columns.clear();
+        self.update_cursor();
+        changed
+    }
 
-                        if delete_line == false {
-                            let x = if self.cursor_rect.0 > self.advance_width { self.cursor_rect.0 - self.advance_width } else { 0 };
-                            self.set_cursor_offset_from_pos((x, self.cursor_rect.1 + 10));
-                        } else {
-                            if number_of_chars_on_prev_line == 0 {
-                                self.set_cursor_offset_from_pos((0, self.cursor_rect.1 - 5));
-                            } else {
-                                self.set_cursor_offset_from_pos((number_of_chars_on_prev_line * self.advance_width - 2, self.cursor_rect.1 - 5));
-                            }
-                        }
-                    }
-                    self.undo_stack.add(undo, undo_pos, self.text.clone(), self.cursor_pos);
-                    return  true;
-                },
+    /// Inserts the text at every cursor, replacing the selected text
+    fn insert_at_cursors(&mut self, text: &str) {
+        self.edit_selections(|_, _, selection| Some((selection.start(), selection.end(), text.to_string())));
+    }
 
-                WidgetKey::Tab => {
-                    self.text.insert(self.cursor_offset.min(self.text.len()), ' ');
-                    self.text.insert((self.cursor_offset + 1).min(self.text.len()), ' ');
-                    self.process_text();
-                    self.set_cursor_offset_from_pos((self.cursor_rect.0 + self.advance_width * 2, self.cursor_rect.1 + 10));
-                    self.undo_stack.add(undo, undo_pos, self.text.clone(), self.cursor_pos);
-                    return  true;
-                },
+    /// One level of indentation
+    fn indent_unit(&self) -> String {
+        if self.settings.insert_spaces { " ".repeat(self.settings.tab_width.max(1)) } else { "\t".to_string() }
+    }
 
-                WidgetKey::Return => {
-                    self.text.insert(self.cursor_offset.min(self.text.len()), '\n');
-                    self.process_text();
-                    self.set_cursor_offset_from_pos((0, self.cursor_rect.1 + 30));
-                    self.undo_stack.add(undo, undo_pos, self.text.clone(), self.cursor_pos);
-                    return  true;
-                },
+    /// Inserts a tab at every cursor, or spaces up to the next tab stop
+    fn insert_tab(&mut self) {
+        let tab_width = self.settings.tab_width.max(1);
+        let insert_spaces = self.settings.insert_spaces;
+        self.edit_selections(|editor, _, selection| {
+            let text = if insert_spaces {
+                let column = editor.text.display_column(editor.text.char_to_pos(selection.start()), tab_width);
+                " ".repeat(tab_width - column % tab_width)
+            } else {
+                "\t".to_string()
+            };
+            Some((selection.start(), selection.end(), text))
+        });
+    }
 
-                WidgetKey::Up => {
-                    if self.cursor_rect.1 >= 5 {
-                        self.set_cursor_offset_from_pos((self.cursor_rect.0, self.cursor_rect.1 - 5));
-                    }
-                    return  true;
-                },
+    /// Indents every line touched by a selection by one level, or outdents them. A selection
+    /// ending at the start of a line does not touch that line. Blank lines are not indented.
+    fn indent_lines(&mut self, outdent: bool) {
+        let mut lines = vec![];
+        for selection in self.selections.iter() {
+            let first = self.text.char_to_line(selection.start());
+            let (column, mut last) = self.text.char_to_pos(selection.end());
+            if column == 0 && last > first {
+                last -= 1;
+            }
+            lines.extend(first..=last);
+        }
+        lines.sort();
+        lines.dedup();
+
+        let unit = self.indent_unit();
+        let tab_width = self.settings.tab_width.max(1);
+
+        // The char index and the amount of inserted (or removed if negative) chars of every edit.
+        // The lines are edited from the last to the first, so the indices stay valid.
+        let mut edits : Vec<(usize, isize)> = vec![];
+
+        for line in lines.into_iter().rev() {
+            let start = self.text.line_to_char(line);
+            let text = self.text.line(line);
+            if outdent {
+                let count = if text.starts_with('\t') { 1 } else { text.chars().take(tab_width).take_while(|c| *c == ' ').count() };
+                if count > 0 {
+                    self.remove_text(start, start + count);
+                    edits.push((start, -(count as isize)));
+                }
+            } else if !text.trim().is_empty() {
+                self.insert_text(start, unit.as_str());
+                edits.push((start, unit.chars().count() as isize));
+            }
+        }
 
-                WidgetKey::Down => {
-                    self.set_cursor_offset_from_pos((self.cursor_rect.0, self.cursor_rect.1 + 30));
-                    return  true;
-                },
+        // The selections move with the text, indices in removed indentation move to the line start
+        let moved = |index: usize| {
+            let mut moved = index as isize;
+            for (start, count) in &edits {
+                if index > *start {
+                    moved += if *count < 0 { -(count.abs().min((index - start) as isize)) } else { *count };
+                }
+            }
+            moved as usize
+        };
+        let selections = self.selections.iter().map(|s| Selection::new(moved(s.anchor), moved(s.head))).collect();
 
-                WidgetKey::Left => {
+        self.selections = SelectionSet::from_vec(selections, self.selections.primary_index());
+        self.desired_columns.clear();
+        self.update_cursor();
+    }
 
-                    if self.logo || self.ctrl {
-                        self.set_cursor_offset_from_pos((0, self.cursor_rect.1 + 10));
-                    } else {
-                        if self.cursor_pos.0 > 0 {//&& self.cursor_rect.0 >= 5 {
-                            // Go one left
-                            if self.cursor_rect.0 > self.advance_width {
-                                self.set_cursor_offset_from_pos((self.cursor_rect.0 - self.advance_width, self.cursor_rect.1 + 10));
-                            } else {
-                                self.set_cursor_offset_from_pos((0, self.cursor_rect.1 + 10));
+    /// Inserts a line break at every cursor. The new line keeps the indentation of the current line
+    /// and is indented one more level after an opening bracket, Return between a pair of brackets
+    /// also moves the closing bracket onto its own line.
+    fn insert_newline(&mut self) {
+        let unit = self.indent_unit();
+        let line_ending = self.text.line_ending();
+        self.edit_and_select(|editor, _, selection| {
+            let line = editor.text.char_to_line(selection.start());
+            let line_start = editor.text.line_to_char(line);
+            let line_end = line_start + editor.text.line(line).chars().count();
+
+            let before = editor.text.slice(line_start, selection.start());
+            let after = editor.text.slice(selection.end(), line_end);
+
+            // Inside the indentation only the part before the cursor is kept
+            let indent : String = before.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+
+            let before = before.trim_end();
+            let after = after.trim_start();
+            let pair = editor.get_language().indent_pairs().iter().find(|(open, _)| before.ends_with(open.as_str())).cloned();
+
+            let mut text = format!("{}{}", line_ending, indent);
+            if pair.is_some() {
+                text += unit.as_str();
+            }
+            let cursor = text.chars().count();
+            if let Some((_, close)) = pair {
+                if after.starts_with(close.as_str()) {
+                    text += format!("{}{}", line_ending, indent).as_str();
+                }
+            }
+            Some((selection.start(), selection.end(), text, Selection::cursor(cursor)))
+        });
+    }
+
+    /// Types a char of an auto closing pair at every cursor. An opening char also inserts the
+    /// closing char or wraps the selection, a closing char steps over the same char after the
+    /// cursor. Returns false if the char is in no pair or there is nothing to step over.
+    fn insert_pair(&mut self, c: char) -> bool {
+        let pairs = self.get_language().auto_close_pairs();
+        let close = pairs.iter().find(|(open, _)| *open == c).map(|(_, close)| *close);
+        let closing = pairs.iter().any(|(_, close)| *close == c);
+
+        let step_over = |editor: &Self, selection: Selection| closing && selection.is_empty() && editor.text.char(selection.head) == Some(c);
+
+        if close.is_none() && !self.selections.iter().any(|selection| step_over(self, *selection)) {
+            return false;
+        }
+
+        self.edit_and_select(|editor, _, selection| {
+            let (start, end) = (selection.start(), selection.end());
+
+            if step_over(editor, selection) {
+                // The char replaces itself, the cursor ends up behind it
+                return Some((start, end + 1, c.to_string(), Selection::cursor(1)));
+            }
+
+            if let Some(close) = close {
+                if !selection.is_empty() {
+                    // Wrap the selection, the text stays selected
+                    let text = editor.text.slice(start, end);
+                    let length = text.chars().count();
+                    let selected = if selection.is_reversed() { Selection::new(length + 1, 1) } else { Selection::new(1, length + 1) };
+                    return Some((start, end, format!("{}{}{}", c, text, close), selected));
+                }
+
+                // Only close before whitespace or a closing char, and not a quote after a word
+                let next = editor.text.char(start);
+                let before_space = next.is_none_or(|next| next.is_whitespace() || pairs.iter().any(|(_, close)| *close == next));
+                let after_word = start > 0 && editor.text.char(start - 1).is_some_and(|prev| prev.is_alphanumeric() || prev == '_');
+
+                if before_space && (c != close || !after_word) {
+                    return Some((start, end, format!("{}{}", c, close), Selection::cursor(1)));
+                }
+            }
+            Some((start, end, c.to_string(), Selection::cursor(1)))
+        });
+        true
+    }
+
+    /// Types a closing bracket on a line which is blank up to the cursor, the line is dedented by
+    /// one level. Returns false if the char is no closing bracket or no cursor is on a blank line.
+    fn insert_closing_bracket(&mut self, c: char) -> bool {
+        let closing = self.get_language().indent_pairs().iter().any(|(_, close)| close.chars().eq(std::iter::once(c)));
+        if !closing {
+            return false;
+        }
+
+        let unit = self.indent_unit();
+        let blank = |editor: &Self, selection: Selection| {
+            let line_start = editor.text.line_to_char(editor.text.char_to_line(selection.head));
+            let indent = editor.text.slice(line_start, selection.head);
+            if selection.is_empty() && !indent.is_empty() && indent.chars().all(|c| c == ' ' || c == '\t') { Some((line_start, indent)) } else { None }
+        };
+
+        if !self.selections.iter().any(|selection| blank(self, *selection).is_some()) {
+            return false;
+        }
+
+        self.edit_selections(|editor, _, selection| {
+            if let Some((line_start, indent)) = blank(editor, selection) {
+                let indent = if let Some(indent) = indent.strip_suffix(unit.as_str()) {
+                    indent.to_string()
+                } else {
+                    // Less than a full level, remove it up to the width of one level
+                    let keep = indent.chars().count().saturating_sub(unit.chars().count());
+                    indent.chars().take(keep).collect()
+                };
+                Some((line_start, selection.head, format!("{}{}", indent, c)))
+            } else {
+                Some((selection.start(), selection.end(), c.to_string()))
+            }
+        });
+        true
+    }
+
+    /// Deletes the selected text, returns false if nothing was selected
+    fn delete_selections(&mut self) -> bool {
+        self.edit_selections(|_, _, selection| if selection.is_empty() { None } else { Some((selection.start(), selection.end(), String::new())) })
+    }
+
+    /// Selects the rectangle between the anchor and head (display column, line) positions, with
+    /// one selection on every line. Columns past the end of a line select up to the line end, a
+    /// tab which is partly inside the rectangle is selected.
+    pub fn set_block_selection(&mut self, anchor: (usize, usize), head: (usize, usize)) {
+        let last_line = self.text.len_lines() - 1;
+        let anchor = (anchor.0, anchor.1.min(last_line));
+        let head = (head.0, head.1.min(last_line));
+
+        let lines : Vec<usize> = if anchor.1 <= head.1 { (anchor.1..=head.1).collect() } else { (head.1..=anchor.1).rev().collect() };
+        let selections : Vec<Selection> = lines.iter().map(|line| {
+            let columns = self.text.display_columns(*line, self.settings.tab_width);
+            // The left edge rounds down and the right edge rounds up to a grapheme boundary
+            let left = |display: usize| columns.iter().rposition(|c| *c <= display).unwrap_or(0);
+            let right = |display: usize| columns.iter().position(|c| *c >= display).unwrap_or(columns.len() - 1);
+            let (anchor_column, head_column) = if anchor.0 <= head.0 { (left(anchor.0), right(head.0)) } else { (right(anchor.0), left(head.0)) };
+            Selection::new(self.text.pos_to_char((anchor_column, *line)), self.text.pos_to_char((head_column, *line)))
+        }).collect();
+
+        // The selection on the line of the head holds the cursor
+        let primary = selections.len() - 1;
+        self.selections = SelectionSet::from_vec(selections, primary);
+        self.desired_columns.clear();
+        self.update_cursor();
+        self.block = Some((anchor, head));
+    }
+
+    /// Extends the block selection, or starts one at the cursor, by one line or by one grapheme of
+    /// the line of the head
+    fn extend_block(&mut self, key: WidgetKey) {
+        let tab_width = self.settings.tab_width;
+        let cursor = (self.text.display_column(self.cursor_pos, tab_width), self.cursor_pos.1);
+        let (anchor, head) = self.block.unwrap_or((cursor, cursor));
+        let columns = self.text.display_columns(head.1, tab_width);
+
+        let head = match key {
+            WidgetKey::Up => (head.0, head.1.saturating_sub(1)),
+            WidgetKey::Down => (head.0, head.1 + 1),
+            WidgetKey::Left => {
+                let end = columns[columns.len() - 1];
+                let previous = if head.0 > end { head.0 - 1 } else { columns.iter().rev().find(|c| **c < head.0).copied().unwrap_or(0) };
+                (previous, head.1)
+            },
+            _ => {
+                // Up to the end of the longest line of the block
+                let max_len = (anchor.1.min(head.1)..=anchor.1.max(head.1)).map(|line| self.text.display_column((usize::MAX, line), tab_width)).max().unwrap_or(0);
+                let next = columns.iter().find(|c| **c > head.0).copied().unwrap_or(head.0 + 1);
+                (next.min(max_len.max(head.0)), head.1)
+            }
+        };
+        self.set_block_selection(anchor, head);
+    }
+
+    /// Inserts the lines of the text below each other, starting at the display column of the
+    /// cursor. Short lines are padded with spaces and missing lines are added.
+    fn paste_block(&mut self, text: &str) {
+        self.delete_selections();
+        let tab_width = self.settings.tab_width;
+        let (column, line) = (self.text.display_column(self.cursor_pos, tab_width), self.cursor_pos.1);
+
+        let mut end = self.cursor_offset;
+        for (index, block_line) in text.split('\n').enumerate() {
+            let line = line + index;
+            if line >= self.text.len_lines() {
+                self.insert_text(self.text.len_chars(), "\n");
+            }
+
+            let line_end = self.text.display_column((usize::MAX, line), tab_width);
+            let padding = " ".repeat(column.saturating_sub(line_end));
+            let offset = self.text.pos_to_char((self.text.column_at_display(line, column, tab_width), line));
+
+            let inserted = padding + block_line;
+            self.insert_text(offset, inserted.as_str());
+            end = offset + inserted.chars().count();
+        }
+        self.move_cursor(self.text.char_to_pos(end), false);
+    }
+
+    /// Selects the word at the cursor if the primary selection is empty, otherwise adds a selection
+    /// at the next occurrence of the selected text
+    fn add_next_occurrence(&mut self) {
+        let primary = self.selections.primary();
+
+        if primary.is_empty() {
+            let line = self.cursor_pos.1;
+            if let Some((start, end)) = self.word_at(self.cursor_pos) {
+                self.set_selection((start, line), (end, line));
+            }
+            return;
+        }
+
+        let needle = self.text.slice(primary.start(), primary.end());
+        let text = self.text.to_string();
+        let from = text.char_indices().nth(primary.end()).map(|(index, _)| index).unwrap_or(text.len());
+
+        // Search after the primary selection and wrap around at the end of the text
+        if let Some(index) = text[from..].find(needle.as_str()).map(|index| index + from).or_else(|| text.find(needle.as_str())) {
+            let start = text[..index].chars().count();
+            let selection = Selection::new(start, start + needle.chars().count());
+            if !self.selections.iter().any(|s| s.start() == selection.start()) {
+                self.selections.add(selection);
+                self.desired_columns.clear();
+                self.update_cursor();
+            }
+        }
+    }
+
+    /// The (start, end) columns of the word at the given position, words consist of the
+    /// identifier chars of the language
+    fn word_at(&self, pos: (usize, usize)) -> Option<(usize, usize)> {
+        let language = self.languages.get(self.mode);
+        let is_word : Vec<bool> = self.text.line(pos.1).graphemes(true).map(|g| g.chars().all(|c| language.is_identifier_char(c))).collect();
+
+        // A click on the right half of the last char places the cursor after the word
+        let column = if is_word.get(pos.0) == Some(&true) { pos.0 } else if pos.0 > 0 && is_word.get(pos.0 - 1) == Some(&true) { pos.0 - 1 } else { return None };
+
+        let mut start = column;
+        while start > 0 && is_word[start - 1] {
+            start -= 1;
+        }
+        let mut end = column;
+        while end < is_word.len() && is_word[end] {
+            end += 1;
+        }
+        Some((start, end))
+    }
+
+    /// The class of the grapheme for word movement: whitespace, word or punctuation
+    fn grapheme_class(language: &Language, grapheme: &str) -> u8 {
+        if grapheme.chars().all(|c| c.is_whitespace()) {
+            0
+        } else if grapheme.chars().all(|c| language.is_identifier_char(c)) {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// The position of the previous word start, skips whitespace and moves to the end of the
+    /// previous line at the start of a line
+    fn word_left(&self, pos: (usize, usize)) -> (usize, usize) {
+        let (mut column, line) = pos;
+        if column == 0 {
+            return if line > 0 { (self.text.line_len(line - 1), line - 1) } else { pos };
+        }
+
+        let language = self.languages.get(self.mode);
+        let classes : Vec<u8> = self.text.line(line).graphemes(true).map(|g| Self::grapheme_class(language, g)).collect();
+
+        column = column.min(classes.len());
+        while column > 0 && classes[column - 1] == 0 {
+            column -= 1;
+        }
+        if column > 0 {
+            let class = classes[column - 1];
+            while column > 0 && classes[column - 1] == class {
+                column -= 1;
+            }
+        }
+        (column, line)
+    }
+
+    /// The position after the next word end, skips whitespace and moves to the start of the
+    /// next line at the end of a line
+    fn word_right(&self, pos: (usize, usize)) -> (usize, usize) {
+        let (mut column, line) = pos;
+        let language = self.languages.get(self.mode);
+        let classes : Vec<u8> = self.text.line(line).graphemes(true).map(|g| Self::grapheme_class(language, g)).collect();
+
+        if column >= classes.len() {
+            return if line + 1 < self.text.len_lines() { (0, line + 1) } else { pos };
+        }
+
+        while column < classes.len() && classes[column] == 0 {
+            column += 1;
+        }
+        if column < classes.len() {
+            let class = classes[column];
+            while column < classes.len() && classes[column] == class {
+                column += 1;
+            }
+        }
+        (column, line)
+    }
+
+    /// The column of the first non whitespace char of the line
+    fn first_non_whitespace(&self, line: usize) -> usize {
+        self.text.line(line).graphemes(true).take_while(|g| g.chars().all(|c| c.is_whitespace())).count()
+    }
+
+    /// Moves the current cursor for the given key
+    fn move_key(&mut self, key: WidgetKey, extend: bool) {
+        match key {
+            WidgetKey::Up => {
+                if self.logo {
+                    // Start of the document
+                    self.move_cursor((0, 0), extend);
+                } else {
+                    self.move_cursor_vertically(-1, extend);
+                }
+            },
+
+            WidgetKey::Down => {
+                if self.logo {
+                    // End of the document
+                    let last_line = self.text.len_lines() - 1;
+                    self.move_cursor((self.text.line_len(last_line), last_line), extend);
+                } else {
+                    self.move_cursor_vertically(1, extend);
+                }
+            },
+
+            WidgetKey::PageUp => {
+                let lines = self.page_lines();
+                self.move_cursor_vertically(-(lines as isize), extend);
+            },
+
+            WidgetKey::PageDown => {
+                let lines = self.page_lines();
+                self.move_cursor_vertically(lines as isize, extend);
+            },
+
+            WidgetKey::Left => {
+                let (column, line) = self.cursor_pos;
+                if self.logo {
+                    self.move_cursor((0, line), extend);
+                } else if self.ctrl || self.alt {
+                    self.move_cursor(self.word_left(self.cursor_pos), extend);
+                } else if !extend && !self.selections.primary().is_empty() {
+                    // Collapse the selection to its start
+                    self.move_cursor(self.text.char_to_pos(self.selections.primary().start()), false);
+                } else {
+                    if column > 0 {
+                        // Go one left
+                        self.move_cursor((column - 1, line), extend);
+                    } else if line > 0 {
+                        // Go one up
+                        self.move_cursor((self.text.line_len(line - 1), line - 1), extend);
+                    }
+                }
+            },
+
+            WidgetKey::Right => {
+                let (column, line) = self.cursor_pos;
+                if self.logo {
+                    self.move_cursor((self.text.line_len(line), line), extend);
+                } else if self.ctrl || self.alt {
+                    self.move_cursor(self.word_right(self.cursor_pos), extend);
+                } else if !extend && !self.selections.primary().is_empty() {
+                    // Collapse the selection to its end
+                    self.move_cursor(self.text.char_to_pos(self.selections.primary().end()), false);
+                } else {
+                    if column < self.text.line_len(line) {
+                        // Go Right
+                        self.move_cursor((column + 1, line), extend);
+                    } else if line + 1 < self.text.len_lines() {
+                        // Go down
+                        self.move_cursor((0, line + 1), extend);
+                    }
+                }
+            },
+
+            WidgetKey::Home => {
+                if self.ctrl || self.logo {
+                    self.move_cursor((0, 0), extend);
+                } else {
+                    // Smart home, toggles between the first non whitespace char and the line start
+                    let line = self.cursor_pos.1;
+                    let indent = self.first_non_whitespace(line);
+                    self.move_cursor((if self.cursor_pos.0 == indent { 0 } else { indent }, line), extend);
+                }
+            },
+
+            WidgetKey::End => {
+                let line = if self.ctrl || self.logo { self.text.len_lines() - 1 } else { self.cursor_pos.1 };
+                self.move_cursor((self.text.line_len(line), line), extend);
+            },
+            _ => {}
+        }
+    }
+
+    pub fn key_down(&mut self, char: Option<char>, key: Option<WidgetKey>) -> bool {
+        let consumed = self.process_key(char, key);
+        if consumed {
+            self.scroll_to_cursor();
+        }
+        consumed
+    }
+
+    /// Handles a key press, returns true if the key was consumed
+    fn process_key(&mut self, char: Option<char>, key: Option<WidgetKey>) -> bool {
+
+        if self.find_bar.is_some() && self.find_bar_key(char, key) {
+            return true;
+        }
+
+        let undo = self.selections.clone();
+
+        if self.logo || self.ctrl {
+            use copypasta::{ClipboardContext, ClipboardProvider};
+
+            // Select All
+            if char == Some('a') || char == Some('A') {
+                self.select_all();
+                return true;
+            }
+
+            // Copy
+            if char == Some('c') || char == Some('C') {
+                let clip = self.copy();
+
+                let mut ctx = ClipboardContext::new().unwrap();
+                _ = ctx.set_contents(clip.to_owned());
+
+                return true;
+            }
+
+            // Cut
+            if char == Some('x') || char == Some('X') {
+                let clip = self.cut();
+
+                let mut ctx = ClipboardContext::new().unwrap();
+                _ = ctx.set_contents(clip.to_owned());
+
+                return true;
+            }
+
+            // Find
+            if char == Some('f') || char == Some('F') {
+                self.open_find_bar();
+                return true;
+            }
+
+            // Add the next occurrence of the selection
+            if char == Some('d') || char == Some('D') {
+                self.add_next_occurrence();
+                return true;
+            }
+
+            // Paste
+            if char == Some('v') || char == Some('V') {
+                let mut ctx = ClipboardContext::new().unwrap();
+                if let Some(text) = ctx.get_contents().ok() {
+                    self.paste(text);
+                }
+                return true;
+            }
+        }
+
+        // Movement with shift extends the selection
+        let extend = self.shift;
+
+        if let Some(key) = key {
+            match key {
+                WidgetKey::Delete => {
+                    let pairs = if self.settings.auto_close { self.get_language().auto_close_pairs() } else { vec![] };
+                    self.edit_selections(|editor, _, selection| {
+                        if !selection.is_empty() {
+                            return Some((selection.start(), selection.end(), String::new()));
+                        }
+                        let head = selection.head;
+                        if head > 0 {
+                            // Delete both chars of an empty pair
+                            if let (Some(open), Some(close)) = (editor.text.char(head - 1), editor.text.char(head)) {
+                                if pairs.contains(&(open, close)) {
+                                    return Some((head - 1, head + 1, String::new()));
+                                }
                             }
+                        }
+                        let (column, line) = editor.text.char_to_pos(selection.head);
+                        let indent = editor.text.slice(editor.text.line_to_char(line), selection.head);
+                        if column > 0 && indent.chars().all(|c| c == ' ') {
+                            // Delete the spaces of the indentation back to the previous tab stop
+                            let tab_width = editor.settings.tab_width.max(1);
+                            Some((editor.text.pos_to_char(((column - 1) / tab_width * tab_width, line)), selection.head, String::new()))
+                        } else if column > 0 {
+                            Some((editor.text.pos_to_char((column - 1, line)), selection.head, String::new()))
+                        } else if line > 0 {
+                            // Join with the previous line, a line ending can be \r\n
+                            let crlf = head >= 2 && editor.text.slice(head - 2, head) == "\r\n";
+                            Some((if crlf { head - 2 } else { head - 1 }, head, String::new()))
                         } else {
-                            // Go one up
-                            if self.cursor_rect.1 >= 5 {
-                                self.set_cursor_offset_from_pos((100000, self.cursor_rect.1 - 5));
-                            }
+                            None
                         }
-                    }
+                    });
+                    self.add_undo(undo, EditKind::Delete);
                     return  true;
                 },
 
-                WidgetKey::Right => {
-                    if self.logo || self.ctrl {
-                        self.set_cursor_offset_from_pos((100000, self.cursor_rect.1 + 10));
+                WidgetKey::Tab => {
+                    let multiline = self.selections.iter().any(|s| self.text.char_to_line(s.start()) != self.text.char_to_line(s.end()));
+                    if self.shift || multiline {
+                        self.indent_lines(self.shift);
+                        self.add_undo(undo, EditKind::Indent);
                     } else {
-                        if let Some(c) = self.text.chars().nth(self.cursor_offset) {
-                            if c == '\n' {
-                                // Go down
-                                self.set_cursor_offset_from_pos((0, self.cursor_rect.1 + 30));
-                            } else {
-                                // Go Right
-                                self.set_cursor_offset_from_pos((self.cursor_rect.0 + self.advance_width, self.cursor_rect.1 + 10));
-                            }
-                        }
+                        self.insert_tab();
+                        self.add_undo(undo, EditKind::Whitespace);
+                    }
+                    return  true;
+                },
+
+                WidgetKey::Return => {
+                    self.insert_newline();
+                    self.add_undo(undo, EditKind::Whitespace);
+                    return  true;
+                },
+
+                WidgetKey::Up | WidgetKey::Down | WidgetKey::Left | WidgetKey::Right if self.alt && self.shift => {
+                    self.extend_block(key);
+                    return  true;
+                },
+
+                WidgetKey::Up | WidgetKey::Down | WidgetKey::Left | WidgetKey::Right | WidgetKey::Home | WidgetKey::End | WidgetKey::PageUp | WidgetKey::PageDown => {
+                    // Pages scroll the view once for all cursors
+                    if key == WidgetKey::PageUp {
+                        self.offset.1 = (self.offset.1 - self.page_lines() as isize).max(0);
+                    } else if key == WidgetKey::PageDown {
+                        self.offset.1 += self.page_lines() as isize;
+                        self.offset_sanity_check();
                     }
+                    self.move_cursors(|editor| editor.move_key(key, extend));
+                    return  true;
+                },
+
+                WidgetKey::Escape if self.selections.len() > 1 => {
+                    self.clear_secondary_selections();
                     return  true;
                 },
                 _ => {}
@@ -787,35 +1684,15 @@ impl CodeEditor {
         }
 
         if let Some(c) = char {
-            if c.is_ascii() && c.is_control() == false {
-
-                let mut handled = false;
-                if let Some(start) = self.range_start {
-                    if let Some(end) = self.range_end {
-                        let first_half = self.copy_range(None, Some((std::cmp::max(start.0, 0), start.1)));
-                        let second_half = self.copy_range(Some((end.0 + 1, end.1)), None);
-                        let text = first_half + c.to_string().as_str() + second_half.as_str();
-                        self.text = text;
-                        self.range_start = None;
-                        self.range_end = None;
-                        self.process_text();
-                        handled = true;
-
-                        self.set_cursor((start.0 + 1, start.1));
-                    }
-                }
+            if !c.is_control() {
 
-                if handled == false {
-                    if self.text.is_empty() {
-                        self.text.push(c);
-                    } else {
-                        self.text.insert(self.cursor_offset.min(self.text.len()), c);
-                    }
-                    self.process_text();
-                    self.set_cursor_offset_from_pos((self.cursor_rect.0 + self.advance_width, self.cursor_rect.1 + 10));
+                // A combining mark merges into the previous grapheme, cursors are placed by char
+                let paired = self.settings.auto_close && self.insert_pair(c);
+                if !paired && !self.insert_closing_bracket(c) {
+                    self.insert_at_cursors(c.to_string().as_str());
                 }
 
-                self.undo_stack.add(undo, undo_pos, self.text.clone(), self.cursor_pos);
+                self.add_undo(undo, if c.is_whitespace() { EditKind::Whitespace } else { EditKind::Typing });
 
                 return true;
             }
@@ -830,99 +1707,44 @@ impl CodeEditor {
 
         let time = self.get_time();
 
+        // Clicking into the text accepts the match of the find bar
+        self.close_find_bar(false);
+
         if time - self.last_click > 500 {
-            let consumed = self.set_cursor_offset_from_pos((pos.0 - self.settings.line_number_width + self.offset.0 as usize * self.advance_width as usize, pos.1 + self.offset.1 as usize * self.advance_height as usize));
-            self.range_buffer = self.cursor_pos.clone();
-            self.range_start = Some(self.cursor_pos.clone());
-            self.range_end = None;
-            self.needs_update = true;
+            let area_pos = (pos.0 - self.settings.line_number_width + self.offset.0 as usize * self.advance_width, pos.1 + self.offset.1 as usize * self.advance_height);
+            let text_pos = self.screen_to_pos(area_pos);
+            if self.alt {
+                // Alt click adds a cursor, dragging selects a block
+                self.add_selection(text_pos, text_pos);
+                self.block_drag = Some(self.screen_to_grid(area_pos));
+            } else {
+                // Shift click extends the selection
+                self.move_cursor(text_pos, self.shift);
+            }
             self.last_click = time;
             self.click_stage = 0;
-            return consumed;
+            return true;
         } else {
 
             if self.click_stage == 0 {
-
+                // Double click selects the word
                 let line = self.cursor_pos.1;
+                if let Some((start, end)) = self.word_at(self.cursor_pos) {
+                    self.set_selection((start, line), (end, line));
 
-                let mut left =  self.cursor_pos.0;
-                let mut right = self.cursor_pos.0;
-
-                let mut range_start = Some((left, line));
-                let mut range_end = Some((right, line));
-
-                let t = self.copy_range_incl(range_start, range_end);
-                let c = t.chars().next();
-
-                if let Some(c) = c {
-                    if c.is_alphanumeric() {
-
-                        // Go left
-
-                        while left > 0 {
-                            left -= 1;
-                            let r_start = Some((left, line));
-                            let t = self.copy_range_incl(r_start, range_end);
-                            let c = t.chars().next();
-
-                            if let Some(c) = c {
-                                if c.is_alphanumeric() == false {
-                                    left += 1;
-                                    range_start = Some((left, line));
-                                    break;
-                                } else {
-                                    range_start = Some((left, line));
-                                }
-                            }
-                        }
-
-                        // Go Right
-
-                        let mut last_t = "".to_string();
-
-                        loop {
-                            right += 1;
-                            let r_end = Some((right, line));
-                            let t = self.copy_range_incl(range_start, r_end);
-                            let c = t.chars().last();
-
-                            if t.chars().count() <= 1 {
-                                break;
-                            }
-
-                            if let Some(c) = c {
-                                if c.is_alphanumeric() == false {
-                                    right -= 1;
-                                    range_end = Some((right, line));
-                                    break;
-                                } else {
-                                    if t == last_t {
-                                        range_end = Some((100000, line));
-                                        break;
-                                    }
-                                }
-                            } else {
-                                range_end = Some((100000, line));
-                                break;
-                            }
-                            last_t = t.clone();
-                        }
-
-                        self.range_start = range_start;
-                        self.range_end = range_end;
-                        self.needs_update = true;
-
-                        self.last_click = time;
-                        self.click_stage = 1;
+                    self.last_click = time;
+                    self.click_stage = 1;
 
-                        return true;
-                    }
+                    return true;
                 }
             } else {
+                // Triple click selects the line including its line break
                 let line = self.cursor_pos.1;
-                self.range_start = Some((0, line));
-                self.range_end = Some((100000, line));
-                self.needs_update = true;
+                if line + 1 < self.text.len_lines() {
+                    self.set_selection((0, line), (0, line + 1));
+                } else {
+                    self.set_selection((0, line), (self.text.line_len(line), line));
+                }
                 self.click_stage = 2;
                 return  true;
             }
@@ -931,12 +1753,8 @@ impl CodeEditor {
     }
 
     pub fn mouse_up(&mut self, _pos: (usize, usize)) -> bool {
-        if self.range_start.is_none() || self.range_end.is_none() {
-            self.range_start = None;
-            self.range_end = None;
-            self.needs_update = true;
-        }
         self.drag_pos = None;
+        self.block_drag = None;
         false
     }
 
@@ -945,30 +1763,22 @@ impl CodeEditor {
             pos.0 = self.settings.line_number_width;
         }
 
-        let consumed = self.set_cursor_offset_from_pos((pos.0 - self.settings.line_number_width + self.offset.0 as usize * self.advance_width as usize, pos.1 + self.offset.1 as usize * self.advance_height as usize));
-
-        if (self.cursor_pos.1 == self.range_buffer.1 && self.cursor_pos.0 <= self.range_buffer.0) || self.cursor_pos.1 < self.range_buffer.1 {
-            self.range_start = Some(self.cursor_pos.clone());
-            let mut end = self.range_buffer.clone();
-            if end.0 > 0 { end.0 -= 1; }
-            self.range_end = Some(end);
-        } else {
-            if self.range_end.is_some() {
-                self.range_start = Some(self.range_buffer);
-                let mut end = self.cursor_pos.clone();
-                if end.0 > 0 { end.0 -= 1; }
-                self.range_end = Some(end);
-            } else {
-                let mut end = self.cursor_pos.clone();
-                if end.0 > 0 { end.0 -= 1; }
-                self.range_end = Some(end);
+        let area_pos = (pos.0 - self.settings.line_number_width + self.offset.0 as usize * self.advance_width, pos.1 + self.offset.1 as usize * self.advance_height);
+        if let Some(anchor) = self.block_drag {
+            let head = self.screen_to_grid(area_pos);
+            // A click without moving keeps the added cursor
+            if head != anchor || self.block.is_some() {
+                self.set_block_selection(anchor, head);
             }
+        } else {
+            let text_pos = self.screen_to_pos(area_pos);
+            self.move_cursor(text_pos, true);
         }
 
         self.drag_pos = Some(pos);
 
         self.needs_update = true;
-        consumed
+        true
     }
 
     pub fn mouse_hover(&mut self, _pos: (usize, usize)) -> bool {
@@ -993,17 +1803,17 @@ impl CodeEditor {
     /// Makes sure that the offset is within a reasonable range.
     pub fn offset_sanity_check(&mut self) {
         // If the editors width is larger than the text width dont scroll.
-        if self.code_safe_rect.2 >= self.text_buffer_size.0 {
+        if self.code_safe_rect.2 >= self.text_size.0 {
             self.offset.0 = 0;
         } else {
             // Make sure only to scroll as much as needed
-            let max_scroll_x = (self.text_buffer_size.0 - self.code_safe_rect.2) / self.advance_width;
+            let max_scroll_x = (self.text_size.0 - self.code_safe_rect.2) / self.advance_width;
             if self.offset.0 > max_scroll_x as isize {
                 self.offset.0 = max_scroll_x as isize;
             }
         }
 
-        let y_height = self.text_buffer_size.1;// - self.settings.line_number_width;
+        let y_height = self.text_size.1;// - self.settings.line_number_width;
 
         // If the editors height is larger than the text height dont scroll.
         if self.code_safe_rect.3 >= y_height {
@@ -1034,74 +1844,274 @@ impl CodeEditor {
             stop.as_millis()
     }
 
-    /// Cut
-    pub fn cut(&mut self) -> String {
-        let undo = self.text.clone();
+    /// Starts a search, all matches are highlighted. Returns the amount of matches or the error of
+    /// an invalid regular expression.
+    pub fn set_search(&mut self, query: &str, options: SearchOptions) -> Result<usize, String> {
+        let mut search = Search::new(query, options)?;
+        let count = search.matches(&self.text).len();
+        self.search = Some(search);
+        self.needs_update = true;
+        Ok(count)
+    }
+
+    /// Ends the search and removes the highlights
+    pub fn clear_search(&mut self) {
+        self.search = None;
+        self.needs_update = true;
+    }
+
+    /// The (start, end) char indices of all matches of the search
+    pub fn find_all(&mut self) -> Vec<(usize, usize)> {
+        if let Some(search) = &mut self.search {
+            search.matches(&self.text).clone()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Selects the next match after the cursor, returns false if there is none
+    pub fn find_next(&mut self) -> bool {
+        let matches = self.find_all();
+        let end = self.selections.primary().end();
+
+        let next = matches.iter().find(|m| m.0 >= end).or(if self.wrap_around() { matches.first() } else { None });
+        if let Some(m) = next.copied() {
+            self.select_match(m);
+            return true;
+        }
+        false
+    }
+
+    /// Selects the previous match before the cursor, returns false if there is none
+    pub fn find_previous(&mut self) -> bool {
+        let matches = self.find_all();
+        let start = self.selections.primary().start();
+
+        let previous = matches.iter().rev().find(|m| m.0 < start).or(if self.wrap_around() { matches.last() } else { None });
+        if let Some(m) = previous.copied() {
+            self.select_match(m);
+            return true;
+        }
+        false
+    }
+
+    /// Replaces the selected match and selects the next one. If no match is selected the next one
+    /// is only selected. Returns true if a match was replaced.
+    pub fn replace(&mut self, replacement: &str) -> bool {
+        let selection = self.selections.primary();
+        let selected = (selection.start(), selection.end());
+
+        if self.find_all().contains(&selected) {
+            let undo = self.selections.clone();
+
+            let text = self.replacement(selected, replacement);
+            self.remove_text(selected.0, selected.1);
+            self.insert_text(selected.0, text.as_str());
+            self.move_cursor(self.text.char_to_pos(selected.0 + text.chars().count()), false);
+
+            self.add_undo(undo, EditKind::Replace);
+            self.find_next();
+            return true;
+        }
+
+        self.find_next();
+        false
+    }
+
+    /// Replaces all matches as one undo step, returns the amount of replaced matches
+    pub fn replace_all(&mut self, replacement: &str) -> usize {
+        let replacements = if let Some(search) = &mut self.search { search.replacements(&self.text, replacement) } else { vec![] };
+        if replacements.is_empty() {
+            return 0;
+        }
+
+        let undo = self.selections.clone();
         let undo_pos = self.cursor_pos;
-        let text = self.copy_range_incl(self.range_start, self.range_end);
 
-        if let Some(start) = self.range_start {
-            if let Some(end) = self.range_end {
-                let first_half = self.copy_range(None, Some((std::cmp::max(start.0, 0), start.1)));
-                let second_half = self.copy_range(Some((end.0 + 1, end.1)), None);
-                let text = first_half + second_half.as_str();
-                self.text = text;
+        // Replace from the end so that the char indices of the remaining matches stay valid
+        for (start, end, text) in replacements.iter().rev() {
+            self.remove_text(*start, *end);
+            self.insert_text(*start, text.as_str());
+        }
+
+        self.set_cursor(undo_pos);
+        self.add_undo(undo, EditKind::Replace);
+        replacements.len()
+    }
+
+    /// The text replacing the match, with the captures of a regular expression expanded
+    fn replacement(&mut self, m: (usize, usize), replacement: &str) -> String {
+        if let Some(search) = &mut self.search {
+            search.replacement(&self.text, m.0, m.1, replacement)
+        } else {
+            replacement.to_string()
+        }
+    }
+
+    fn wrap_around(&self) -> bool {
+        self.search.as_ref().is_none_or(|search| search.options.wrap_around)
+    }
+
+    /// Selects the match and scrolls it into view
+    fn select_match(&mut self, m: (usize, usize)) {
+        self.set_selection(self.text.char_to_pos(m.0), self.text.char_to_pos(m.1));
+        self.scroll_to_cursor();
+    }
+
+    /// Opens the find bar, the key input goes to its query until it is closed. A selection on a
+    /// single line becomes the query.
+    pub fn open_find_bar(&mut self) {
+        let selected = self.get_selected_text();
+        let query = if !selected.is_empty() && !selected.contains('\n') {
+            selected
+        } else {
+            self.search.as_ref().map(|search| search.query.clone()).unwrap_or_default()
+        };
+
+        self.find_bar = Some(FindBar {
+            query,
+            options                 : self.search.as_ref().map(|search| search.options).unwrap_or_default(),
 
-                self.range_start = None;
-                self.range_end = None;
-                self.process_text();
+            restore_selections      : self.selections.clone(),
+            restore_offset          : self.offset,
+        });
+        self.find_bar_search();
+    }
 
-                self.set_cursor((start.0, start.1));
+    /// Closes the find bar, with restore the cursor and scroll offset from before the search are
+    /// restored, otherwise the found match stays selected
+    pub fn close_find_bar(&mut self, restore: bool) {
+        if let Some(bar) = self.find_bar.take() {
+            if restore {
+                self.selections = bar.restore_selections;
+                self.desired_columns.clear();
+                self.update_cursor();
+                self.offset = bar.restore_offset;
             }
+            self.clear_search();
         }
-        self.undo_stack.add(undo, undo_pos, self.text.clone(), self.cursor_pos);
-        text
     }
 
-    /// Copy
-    pub fn copy(&mut self) -> String {
-        self.copy_range_incl(self.range_start, self.range_end)
+    pub fn is_find_bar_open(&self) -> bool {
+        self.find_bar.is_some()
     }
 
-    /// Paste
-    pub fn paste(&mut self, text: String) {
-        let undo = self.text.clone();
-        let undo_pos = self.cursor_pos;
+    /// Searches for the query of the find bar, starting at the cursor from before the search
+    fn find_bar_search(&mut self) {
+        let Some(bar) = &self.find_bar else {
+            return;
+        };
+        let query = bar.query.clone();
+        let options = bar.options;
+
+        self.selections = bar.restore_selections.clone();
+        self.desired_columns.clear();
+        self.update_cursor();
+
+        if query.is_empty() || self.set_search(query.as_str(), options).is_err() {
+            self.search = None;
+            return;
+        }
 
-        let mut handled = false;
-        if let Some(start) = self.range_start {
-            if let Some(end) = self.range_end {
-                let first_half = self.copy_range(None, Some((std::cmp::max(start.0, 0), start.1)));
-                let second_half = self.copy_range(Some((end.0 + 1, end.1)), None);
-                let text = first_half + text.to_string().as_str() + second_half.as_str();
+        // Start at the beginning of the selection, which may already be a match
+        let start = self.selections.primary().start();
+        self.move_cursor(self.text.char_to_pos(start), false);
+        if !self.find_next() {
+            if let Some(bar) = &self.find_bar {
+                self.selections = bar.restore_selections.clone();
+            }
+            self.update_cursor();
+        }
+    }
 
-                self.text = text;
-                self.range_start = None;
-                self.range_end = None;
-                self.process_text();
-                handled = true;
+    /// Handles the key input while the find bar is open, returns false if the key closed the bar
+    /// and still needs to be handled by the editor
+    fn find_bar_key(&mut self, char: Option<char>, key: Option<WidgetKey>) -> bool {
+        if let Some(key) = key {
+            match key {
+                WidgetKey::Escape => {
+                    self.close_find_bar(true);
+                },
+                WidgetKey::Return => {
+                    if self.shift {
+                        self.find_previous();
+                    } else {
+                        self.find_next();
+                    }
+                },
+                WidgetKey::Delete => {
+                    if let Some(bar) = &mut self.find_bar {
+                        bar.query.pop();
+                    }
+                    self.find_bar_search();
+                },
+                WidgetKey::Up | WidgetKey::Down | WidgetKey::Left | WidgetKey::Right | WidgetKey::Home | WidgetKey::End | WidgetKey::PageUp | WidgetKey::PageDown => {
+                    // Moving the cursor accepts the match
+                    self.close_find_bar(false);
+                    return false;
+                },
+                _ => {}
+            }
+            return true;
+        }
 
-                self.set_cursor((start.0, start.1));
+        if let Some(c) = char {
+            if self.logo || self.ctrl {
+                // Paste into the query
+                if c == 'v' || c == 'V' {
+                    use copypasta::{ClipboardContext, ClipboardProvider};
+                    let mut ctx = ClipboardContext::new().unwrap();
+                    if let Ok(text) = ctx.get_contents() {
+                        if let Some(bar) = &mut self.find_bar {
+                            bar.query.push_str(text.lines().next().unwrap_or_default());
+                        }
+                        self.find_bar_search();
+                    }
+                }
+            } else if !c.is_control() {
+                if let Some(bar) = &mut self.find_bar {
+                    bar.query.push(c);
+                }
+                self.find_bar_search();
             }
         }
+        true
+    }
 
-        if handled == false {
-            let half = self.cursor_pos.clone();
+    /// Cut
+    pub fn cut(&mut self) -> String {
+        let undo = self.selections.clone();
+        let text = self.copy();
 
-            let first_half = self.copy_range(None, Some(half));
-            let second_half = self.copy_range(Some(self.cursor_pos), None);
+        if self.delete_selections() {
+            self.add_undo(undo, EditKind::Cut);
+            self.scroll_to_cursor();
+        }
+        text
+    }
 
-            let new_text = first_half + text.as_str();
+    /// Copy, the text of a block selection is remembered to be pasted as a block
+    pub fn copy(&mut self) -> String {
+        let text = self.get_selected_text();
+        self.block_clipboard = if self.block.is_some() { Some(text.clone()) } else { None };
+        text
+    }
 
-            self.text = new_text.clone();
-            self.process_text();
-            self.set_cursor(self.last_pos);
+    /// Paste, if the text has one line for every cursor each cursor gets its own line
+    pub fn paste(&mut self, text: String) {
+        let undo = self.selections.clone();
 
-            self.text = new_text + second_half.as_str();
-            self.needs_update = true;
+        let lines : Vec<&str> = text.split('\n').collect();
+        if self.selections.len() == 1 && self.block_clipboard.as_deref() == Some(text.as_str()) {
+            self.paste_block(text.as_str());
+        } else if self.selections.len() > 1 && lines.len() == self.selections.len() {
+            self.edit_selections(|_, index, selection| Some((selection.start(), selection.end(), lines[index].to_string())));
+        } else {
+            self.insert_at_cursors(text.as_str());
         }
 
-        self.undo_stack.add(undo, undo_pos, self.text.clone(), self.cursor_pos);
+        self.add_undo(undo, EditKind::Paste);
+        self.scroll_to_cursor();
     }
 
     /// Has Undo
@@ -1114,26 +2124,158 @@ impl CodeEditor {
         self.undo_stack.has_redo()
     }
 
-    /// Undo
+    /// Undo, restores the selections from before the undone edits
     pub fn undo(&mut self) {
         if self.undo_stack.has_undo() {
-            let rc = self.undo_stack.undo();
-            self.text = rc.0;
-            self.process_text();
-            self.set_cursor(rc.1);
-            self.needs_update = true;
+            let undo = self.undo_stack.undo();
+            let edits = undo.edits.clone();
+            let selections = undo.undo_selections.clone();
+
+            for edit in edits.iter().rev() {
+                self.remove_text(edit.index, edit.index + edit.inserted.chars().count());
+                self.insert_text(edit.index, edit.removed.as_str());
+            }
+            self.edits.clear();
+            self.restore_selections(selections);
         }
     }
 
-    /// Redo
+    /// Redo, restores the selections from after the redone edits
     pub fn redo(&mut self) {
         if self.undo_stack.has_redo() {
-            let rc = self.undo_stack.redo();
-            self.text = rc.0;
-            self.process_text();
-            self.set_cursor(rc.1);
-            self.needs_update = true;
+            let undo = self.undo_stack.redo();
+            let edits = undo.edits.clone();
+            let selections = undo.redo_selections.clone();
+
+            for edit in edits.iter() {
+                self.remove_text(edit.index, edit.index + edit.removed.chars().count());
+                self.insert_text(edit.index, edit.inserted.as_str());
+            }
+            self.edits.clear();
+            self.restore_selections(selections);
+        }
+    }
+
+    /// Returns the labels and time stamps of the undo steps and the amount of applied steps
+    pub fn get_undo_history(&self) -> UndoHistory {
+        self.undo_stack.history()
+    }
+
+    /// Undoes or redoes steps until the given amount of steps of the undo history is applied
+    pub fn jump_to_undo(&mut self, position: usize) {
+        let position = position.min(self.undo_stack.stack.len());
+        while self.undo_stack.position() > position {
+            self.undo();
+        }
+        while self.undo_stack.position() < position {
+            self.redo();
+        }
+    }
+
+    /// Returns the branches of the undo tree which replace the steps after the applied ones, they
+    /// are only kept if settings.undo_tree is enabled
+    pub fn get_undo_branches(&self) -> Vec<UndoBranch> {
+        self.undo_stack.branches(self.undo_stack.position())
+    }
+
+    /// Makes the given branch of get_undo_branches() the redo steps and redoes all of them, the
+    /// previous redo steps become a branch. Returns false if there is no such branch.
+    pub fn switch_undo_branch(&mut self, branch: usize) -> bool {
+        if !self.undo_stack.switch_branch(self.undo_stack.position(), branch) {
+            return false;
+        }
+        self.jump_to_undo(self.undo_stack.stack.len());
+        true
+    }
+
+    /// Goes back to the state of the text before the last step in the order the steps were made,
+    /// across the branches of the undo tree. Returns false at the oldest state.
+    pub fn undo_earlier(&mut self) -> bool {
+        let seq = self.undo_stack.seq();
+        match self.undo_stack.seqs().into_iter().filter(|s| *s < seq).max() {
+            Some(target) => self.jump_to_seq(target),
+            None if seq > 0 => { self.jump_to_undo(0); true },
+            None => false,
+        }
+    }
+
+    /// Goes forward to the state of the text after the next step in the order the steps were
+    /// made, across the branches of the undo tree. Returns false at the newest state.
+    pub fn undo_later(&mut self) -> bool {
+        let seq = self.undo_stack.seq();
+        match self.undo_stack.seqs().into_iter().filter(|s| *s > seq).min() {
+            Some(target) => self.jump_to_seq(target),
+            None => false,
         }
     }
 
+    /// Switches to the branches which contain the step with the given sequence number and applies
+    /// the steps up to it
+    fn jump_to_seq(&mut self, seq: usize) -> bool {
+        if let Some((path, position)) = self.undo_stack.find(seq) {
+            if let Some((first, _)) = path.first() {
+                if self.undo_stack.position() > *first {
+                    self.jump_to_undo(*first);
+                }
+            }
+            for (at, branch) in path {
+                self.undo_stack.switch_branch(at, branch);
+            }
+            self.jump_to_undo(position);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Saves the undo history as JSON, to be restored by load_undo_history() when the same text is
+    /// edited again. The history is at most settings.undo_file_size bytes long.
+    pub fn save_undo_history(&self) -> Result<String, String> {
+        self.undo_stack.save(self.text.hash(), self.settings.undo_file_size)
+    }
+
+    /// Restores an undo history saved by save_undo_history(), call it after set_text(). Fails if
+    /// the history was saved for a different text.
+    pub fn load_undo_history(&mut self, json: &str) -> Result<(), String> {
+        if json.len() > self.settings.undo_file_size {
+            return Err("The undo history is too large".to_string());
+        }
+        self.undo_stack.load(json, self.text.hash())
+    }
+
+    /// Sets the selections of an undo step and scrolls the cursor into view
+    fn restore_selections(&mut self, selections: SelectionSet) {
+        let length = self.text.len_chars();
+        let clamped = selections.iter().map(|s| Selection::new(s.anchor.min(length), s.head.min(length))).collect();
+        self.selections = SelectionSet::from_vec(clamped, selections.primary_index());
+        self.desired_columns.clear();
+        self.update_cursor();
+        self.scroll_to_cursor();
+    }
+
+    /// Starts an undo group, all edits until the matching end_undo_group() are undone in a single
+    /// step. Groups can be nested.
+    pub fn begin_undo_group(&mut self) {
+        self.undo_stack.begin_group();
+    }
+
+    /// Ends the undo group started by begin_undo_group()
+    pub fn end_undo_group(&mut self) {
+        self.undo_stack.end_group();
+    }
+
+    /// Replaces the clock of the undo time stamps, e.g. with a fixed time for tests
+    pub fn set_undo_clock(&mut self, clock: Clock) {
+        self.undo_stack.set_clock(clock);
+    }
+
+    /// Adds the edits since the last undo step as a step of the given kind, undo are the
+    /// selections before the edits
+    fn add_undo(&mut self, undo: SelectionSet, kind: EditKind) {
+        let edits = std::mem::take(&mut self.edits);
+        self.undo_stack.tree = self.settings.undo_tree;
+        self.undo_stack.add(edits, kind, undo, self.selections.clone());
+        self.undo_stack.limit(self.settings.undo_entries, self.settings.undo_memory);
+    }
+
 }
\ No newline at end of file
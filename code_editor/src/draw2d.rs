@@ -52,6 +52,23 @@ impl Draw2D {
         }
     }
 
+    /// Blend the given rectangle and honors the safe rect
+    pub fn blend_rect_safe(&self, frame: &mut [u8], rect: &(isize, isize, usize, usize), stride: usize, color: &[u8; 4], safe_rect: &(usize, usize, usize, usize)) {
+        let dest_stride_isize = stride as isize;
+        for y in rect.1..rect.1+rect.3 as isize {
+            if y >= safe_rect.1 as isize && y < (safe_rect.1 + safe_rect.3) as isize {
+                for x in rect.0..rect.0+rect.2 as isize{
+                    if x >= safe_rect.0 as isize && x < (safe_rect.0 + safe_rect.2) as isize {
+                        let i = (x * 4 + y * dest_stride_isize * 4) as usize;
+
+                        let background = &[frame[i], frame[i+1], frame[i+2], frame[i+3]];
+                        frame[i..i + 4].copy_from_slice(&self.mix_color(background, color, color[3] as f64 / 255.0));
+                    }
+                }
+            }
+        }
+    }
+
     /// Blend the given rectangle
     pub fn blend_rect(&self, frame: &mut [u8], rect: &(usize, usize, usize, usize), stride: usize, color: &[u8; 4]) {
         for y in rect.1..rect.1+rect.3 {
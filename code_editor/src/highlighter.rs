@@ -0,0 +1,116 @@
+use crate::prelude::*;
+
+/// The scanned tokens of a single line.
+#[derive(Clone)]
+struct HighlightedLine {
+    start_state             : LineState,
+    end_state               : LineState,
+    tokens                  : Vec<TokenSpan>,
+    valid                   : bool,
+}
+
+impl HighlightedLine {
+
+    fn new() -> Self {
+        Self {
+            start_state     : LineState::Normal,
+            end_state       : LineState::Normal,
+            tokens          : vec![],
+            valid           : false,
+        }
+    }
+}
+
+/// Keeps the tokens of every line together with the scanner state at the start of the line.
+/// After an edit only the changed lines are scanned again, scanning continues with the following
+/// lines until their start state is the same as before (the states converge).
+pub struct Highlighter {
+    lines                   : Vec<HighlightedLine>,
+
+    /// All lines before this one are valid
+    first_dirty             : usize,
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Highlighter {
+
+    pub fn new() -> Self {
+        Self {
+            lines           : vec![HighlightedLine::new()],
+            first_dirty     : 0,
+        }
+    }
+
+    /// Invalidates all lines, used when the whole text changes
+    pub fn reset(&mut self, line_count: usize) {
+        self.lines = vec![HighlightedLine::new(); line_count];
+        self.first_dirty = 0;
+    }
+
+    /// The text changed, old_count lines starting at line were replaced by new_count lines
+    pub fn lines_changed(&mut self, line: usize, old_count: usize, new_count: usize) {
+        if line + old_count > self.lines.len() {
+            self.reset(line + new_count);
+            return;
+        }
+        self.lines.splice(line..line + old_count, std::iter::repeat_with(HighlightedLine::new).take(new_count));
+        self.first_dirty = self.first_dirty.min(line);
+    }
+
+    /// Scans the dirty lines up to and including last_line and returns the lines which were scanned.
+    pub fn update(&mut self, text: &TextBuffer, language: &Language, last_line: usize) -> Vec<usize> {
+        let mut scanned = vec![];
+
+        let last_line = last_line.min(self.lines.len().saturating_sub(1));
+        let mut line = self.first_dirty;
+
+        let mut state = if line > 0 && line < self.lines.len() { self.lines[line - 1].end_state } else { LineState::Normal };
+
+        while line <= last_line && line < self.lines.len() {
+            let l = &mut self.lines[line];
+
+            if l.valid && l.start_state == state {
+                // Converged, the line does not need to be scanned again
+                state = l.end_state;
+            } else {
+                let code = text.line(line);
+                let mut scanner = Scanner::with_state(code.as_str(), language, state);
+
+                l.tokens = scanner.scan_spans();
+                l.start_state = state;
+                l.end_state = scanner.state();
+                l.valid = true;
+
+                state = l.end_state;
+                scanned.push(line);
+            }
+            line += 1;
+        }
+
+        self.first_dirty = line;
+        scanned
+    }
+
+    /// The token spans of the given line, the line has to be updated first
+    pub fn line_tokens(&self, line: usize) -> &[TokenSpan] {
+        if let Some(l) = self.lines.get(line) {
+            &l.tokens[..]
+        } else {
+            &[]
+        }
+    }
+
+    /// The scanner state at the start of the given line
+    pub fn line_state(&self, line: usize) -> LineState {
+        if let Some(l) = self.lines.get(line) {
+            l.start_state
+        } else {
+            LineState::Normal
+        }
+    }
+}
@@ -0,0 +1,688 @@
+use std::collections::HashSet;
+
+use serde::{ Deserialize, Serialize };
+
+use crate::prelude::*;
+
+/// A handle to a language registered with the editor. The built-in languages have fixed handles,
+/// languages registered at runtime get their handle from CodeEditor::register_language().
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub struct CodeEditorMode(usize);
+
+#[allow(non_upper_case_globals)]
+impl CodeEditorMode {
+    pub const Rhai          : CodeEditorMode = CodeEditorMode(0);
+    pub const Text          : CodeEditorMode = CodeEditorMode(1);
+    pub const Settings      : CodeEditorMode = CodeEditorMode(2);
+    pub const Rust          : CodeEditorMode = CodeEditorMode(3);
+    pub const Toml          : CodeEditorMode = CodeEditorMode(4);
+    pub const Json          : CodeEditorMode = CodeEditorMode(5);
+    pub const Lua           : CodeEditorMode = CodeEditorMode(6);
+    pub const Python        : CodeEditorMode = CodeEditorMode(7);
+    pub const Glsl          : CodeEditorMode = CodeEditorMode(8);
+    pub const Wgsl          : CodeEditorMode = CodeEditorMode(9);
+    pub const Markdown      : CodeEditorMode = CodeEditorMode(10);
+
+    /// The index of the language inside the registry
+    pub fn id(&self) -> usize {
+        self.0
+    }
+}
+
+/// The number literals a language supports. Decimal integers and fractions are always supported.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default)]
+pub struct NumberFormat {
+    /// 0x1F
+    pub hex                 : bool,
+    /// 0b1010
+    pub binary              : bool,
+    /// 0o17
+    pub octal               : bool,
+    /// 1e10, 2.5E-3
+    pub exponent            : bool,
+    /// 1_000_000
+    pub underscores         : bool,
+}
+
+/// A string without escapes like r#"..."# in Rust or [==[...]==] in Lua. The padding char can be
+/// repeated between the prefix and the open text, the string ends at the close text followed by
+/// the same number of padding chars and the suffix.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default)]
+pub struct RawString {
+    pub prefix              : String,
+    pub padding             : Option<char>,
+    pub open                : String,
+    pub close               : String,
+    pub suffix              : String,
+    /// The string can continue on the following lines
+    pub multiline           : bool,
+    /// Highlight as a block comment, like --[==[...]==] in Lua
+    pub comment             : bool,
+}
+
+impl RawString {
+
+    pub fn new(prefix: &str, padding: Option<char>, open: &str, close: &str, suffix: &str, multiline: bool) -> Self {
+        Self {
+            prefix          : prefix.to_string(),
+            padding,
+            open            : open.to_string(),
+            close           : close.to_string(),
+            suffix          : suffix.to_string(),
+            multiline,
+            comment         : false,
+        }
+    }
+}
+
+/// A delimited piece of text with its own token type, like a heading or emphasis in Markdown or
+/// a table header in TOML.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct DelimitedSpan {
+    pub open                : String,
+    /// None if only the open text is the token, an empty close ends the span at the end of the line
+    #[serde(default)]
+    pub close               : Option<String>,
+    pub kind                : TokenType,
+    /// The span is only recognized at the start of a line, leading whitespace is allowed
+    #[serde(default)]
+    pub line_start          : bool,
+    /// A span without its close text on the same line continues on the following lines
+    #[serde(default)]
+    pub multiline           : bool,
+}
+
+impl DelimitedSpan {
+
+    pub fn new(open: &str, close: Option<&str>, kind: TokenType, line_start: bool) -> Self {
+        Self {
+            open            : open.to_string(),
+            close           : close.map(|c| c.to_string()),
+            kind,
+            line_start,
+            multiline       : false,
+        }
+    }
+}
+
+/// Describes the syntax of a language for highlighting. Definitions can be created in code or
+/// loaded from a TOML or JSON file, all fields are optional.
+///
+/// ```toml
+/// name = "MyDsl"
+/// extensions = ["dsl"]
+/// keywords = ["if", "else", "fn"]
+/// builtins = ["print"]
+/// line_comments = ["#"]
+/// block_comments = [["/*", "*/"]]
+/// strings = ["\""]
+/// brackets = [["(", ")"], ["{", "}"]]
+///
+/// [numbers]
+/// hex = true
+/// ```
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(default)]
+pub struct LanguageDefinition {
+    pub name                : String,
+    /// File extensions without the leading dot
+    pub extensions          : Vec<String>,
+
+    /// If false the text is not highlighted at all
+    pub highlight           : bool,
+
+    pub keywords            : Vec<String>,
+    pub builtins            : Vec<String>,
+
+    /// Chars which are part of identifiers besides letters, digits and '_'
+    pub identifier_chars    : String,
+
+    pub line_comments       : Vec<String>,
+    /// Line comment prefixes of doc comments, checked before the line comments
+    pub doc_comments        : Vec<String>,
+    pub block_comments      : Vec<(String, String)>,
+    /// Block doc comments, checked before the block comments
+    pub doc_block_comments  : Vec<(String, String)>,
+    /// Block comments can contain other block comments
+    pub nested_comments     : bool,
+
+    /// The string delimiters, the same delimiter ends the string
+    pub strings             : Vec<String>,
+    /// The string delimiters whose strings can continue on the following lines. Other strings
+    /// only continue if the line ends with the escape char.
+    pub multiline_strings   : Vec<String>,
+    /// Prefixes which can stand directly before a string delimiter, like b"bytes" or f"{x}"
+    pub string_prefixes     : Vec<String>,
+    pub raw_strings         : Vec<RawString>,
+    /// The escape char inside strings
+    pub escape              : Option<char>,
+    /// Single quoted char literals like 'a' or '\n'
+    pub chars               : bool,
+
+    /// The delimiters of template strings which can contain interpolated expressions
+    pub templates           : Vec<String>,
+    /// The start and end of an expression inside a template string
+    pub interpolation       : Option<(String, String)>,
+
+    pub numbers             : NumberFormat,
+
+    /// Operators with more than one char, the longest match wins
+    pub operators           : Vec<String>,
+
+    /// Opening and closing brackets
+    pub brackets            : Vec<(String, String)>,
+    /// The brackets which indent the lines between them, Return after an opening bracket adds
+    /// one level of indentation. None uses the brackets.
+    pub indent_brackets     : Option<Vec<(String, String)>>,
+    /// The pairs whose closing char is inserted when typing the opening char, pairs of single
+    /// chars only. None uses the brackets, the strings and the templates.
+    pub auto_close          : Option<Vec<(String, String)>>,
+
+    /// Highlight #RRGGBB style colors
+    pub hex_colors          : bool,
+
+    /// Attributes and annotations, an empty close means the open text is followed by a name.
+    /// For example ("#[", "]") in Rust, ("@", "") in Python and ("#", "") for GLSL directives.
+    pub attributes          : Vec<(String, String)>,
+
+    /// A word or string followed by one of these is a key, e.g. "=" in TOML and ":" in JSON
+    pub key_separators      : Vec<String>,
+
+    pub spans               : Vec<DelimitedSpan>,
+
+    /// The text is prose, words, numbers and chars without a rule are plain text
+    pub markup              : bool,
+}
+
+impl Default for LanguageDefinition {
+    fn default() -> Self {
+        Self {
+            name            : "".to_string(),
+            extensions      : vec![],
+
+            highlight       : true,
+
+            keywords        : vec![],
+            builtins        : vec![],
+
+            identifier_chars: "".to_string(),
+
+            line_comments   : vec![],
+            doc_comments    : vec![],
+            block_comments  : vec![],
+            doc_block_comments: vec![],
+            nested_comments : false,
+
+            strings         : vec![],
+            multiline_strings: vec![],
+            string_prefixes : vec![],
+            raw_strings     : vec![],
+            escape          : Some('\\'),
+            chars           : false,
+
+            templates       : vec![],
+            interpolation   : None,
+
+            numbers         : NumberFormat::default(),
+
+            operators       : vec![],
+
+            brackets        : vec![],
+            indent_brackets : None,
+            auto_close      : None,
+
+            hex_colors      : false,
+
+            attributes      : vec![],
+            key_separators  : vec![],
+            spans           : vec![],
+            markup          : false,
+        }
+    }
+}
+
+/// Converts a list of str to a list of Strings
+fn strings(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+/// Converts a list of str pairs to a list of String pairs
+fn pairs(list: &[(&str, &str)]) -> Vec<(String, String)> {
+    list.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect()
+}
+
+impl LanguageDefinition {
+
+    /// Parses a definition in TOML format
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|err| err.to_string())
+    }
+
+    /// Parses a definition in JSON format
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        serde_json::from_str(text).map_err(|err| err.to_string())
+    }
+
+    /// Loads a definition from a .toml or .json file
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        if path.to_lowercase().ends_with(".json") {
+            Self::from_json(text.as_str())
+        } else {
+            Self::from_toml(text.as_str())
+        }
+    }
+
+    /// The brackets which indent the lines between them
+    pub fn indent_pairs(&self) -> &[(String, String)] {
+        self.indent_brackets.as_ref().unwrap_or(&self.brackets)
+    }
+
+    /// The (open, close) chars of the pairs which are closed automatically
+    pub fn auto_close_pairs(&self) -> Vec<(char, char)> {
+        let pairs = match &self.auto_close {
+            Some(pairs) => pairs.clone(),
+            None => {
+                let quotes = self.strings.iter().chain(self.templates.iter()).map(|q| (q.clone(), q.clone()));
+                self.brackets.iter().cloned().chain(quotes).collect()
+            }
+        };
+
+        let single = |s: &str| if s.chars().count() == 1 { s.chars().next() } else { None };
+        let mut chars = vec![];
+        for (open, close) in pairs {
+            if let (Some(open), Some(close)) = (single(open.as_str()), single(close.as_str())) {
+                if !chars.contains(&(open, close)) {
+                    chars.push((open, close));
+                }
+            }
+        }
+        chars
+    }
+
+    /// Rhai, https://rhai.rs
+    pub fn rhai() -> Self {
+        Self {
+            name            : "Rhai".to_string(),
+            extensions      : strings(&["rhai"]),
+            keywords        : strings(&[
+                "as", "break", "catch", "const", "continue", "do", "else", "export", "false", "fn",
+                "for", "global", "if", "import", "in", "let", "loop", "private", "return", "switch",
+                "this", "throw", "true", "try", "until", "while",
+            ]),
+            builtins        : strings(&[
+                "Fn", "call", "curry", "debug", "eval", "is_def_fn", "is_def_var", "is_shared",
+                "print", "type_of",
+            ]),
+            line_comments   : strings(&["//"]),
+            doc_comments    : strings(&["///", "//!"]),
+            block_comments  : pairs(&[("/*", "*/")]),
+            doc_block_comments: pairs(&[("/**", "*/")]),
+            nested_comments : true,
+            strings         : strings(&["\""]),
+            chars           : true,
+            templates       : strings(&["`"]),
+            interpolation   : Some(("${".to_string(), "}".to_string())),
+            numbers         : NumberFormat { hex: true, binary: true, octal: true, exponent: true, underscores: true },
+            operators       : strings(&[
+                "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=", "/=", "%=", "**", "**=",
+                "<<", ">>", "<<=", ">>=", "&=", "|=", "^=", "..", "..=", "::", "=>", "?.", "??", "?[",
+            ]),
+            brackets        : pairs(&[("(", ")"), ("{", "}"), ("[", "]")]),
+            ..Default::default()
+        }
+    }
+
+    /// Plain text without highlighting
+    pub fn text() -> Self {
+        Self {
+            name            : "Text".to_string(),
+            extensions      : strings(&["txt"]),
+            highlight       : false,
+            ..Default::default()
+        }
+    }
+
+    /// Simple key / value settings with hex colors
+    pub fn settings() -> Self {
+        Self {
+            name            : "Settings".to_string(),
+            line_comments   : strings(&["//"]),
+            strings         : strings(&["\"", "`"]),
+            brackets        : pairs(&[("(", ")"), ("{", "}")]),
+            hex_colors      : true,
+            ..Default::default()
+        }
+    }
+
+    /// Rust, https://www.rust-lang.org
+    pub fn rust() -> Self {
+        Self {
+            name            : "Rust".to_string(),
+            extensions      : strings(&["rs"]),
+            keywords        : strings(&[
+                "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+                "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+                "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super",
+                "trait", "true", "type", "unsafe", "use", "where", "while",
+            ]),
+            builtins        : strings(&[
+                "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16",
+                "i32", "i64", "i128", "isize", "f32", "f64", "String", "Vec", "Box", "Option",
+                "Some", "None", "Result", "Ok", "Err",
+            ]),
+            line_comments   : strings(&["//"]),
+            doc_comments    : strings(&["///", "//!"]),
+            block_comments  : pairs(&[("/*", "*/")]),
+            doc_block_comments: pairs(&[("/**", "*/"), ("/*!", "*/")]),
+            nested_comments : true,
+            strings         : strings(&["\""]),
+            multiline_strings: strings(&["\""]),
+            string_prefixes : strings(&["b"]),
+            raw_strings     : vec![
+                RawString::new("br", Some('#'), "\"", "\"", "", true),
+                RawString::new("r", Some('#'), "\"", "\"", "", true),
+            ],
+            chars           : true,
+            numbers         : NumberFormat { hex: true, binary: true, octal: true, exponent: true, underscores: true },
+            operators       : strings(&[
+                "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=", "/=", "%=", "^=", "&=", "|=",
+                "<<", ">>", "<<=", ">>=", "..", "..=", "...", "::", "->", "=>",
+            ]),
+            brackets        : pairs(&[("(", ")"), ("{", "}"), ("[", "]")]),
+            attributes      : pairs(&[("#![", "]"), ("#[", "]")]),
+            ..Default::default()
+        }
+    }
+
+    /// TOML, https://toml.io
+    pub fn toml() -> Self {
+        Self {
+            name            : "TOML".to_string(),
+            extensions      : strings(&["toml"]),
+            keywords        : strings(&["true", "false", "inf", "nan"]),
+            identifier_chars: "-".to_string(),
+            line_comments   : strings(&["#"]),
+            strings         : strings(&["\"\"\"", "\""]),
+            multiline_strings: strings(&["\"\"\""]),
+            raw_strings     : vec![
+                RawString::new("", None, "'''", "'''", "", true),
+                RawString::new("", None, "'", "'", "", false),
+            ],
+            numbers         : NumberFormat { hex: true, binary: true, octal: true, exponent: true, underscores: true },
+            brackets        : pairs(&[("{", "}"), ("[", "]")]),
+            key_separators  : strings(&["="]),
+            spans           : vec![
+                DelimitedSpan::new("[[", Some("]]"), TokenType::Heading, true),
+                DelimitedSpan::new("[", Some("]"), TokenType::Heading, true),
+            ],
+            ..Default::default()
+        }
+    }
+
+    /// JSON, https://www.json.org
+    pub fn json() -> Self {
+        Self {
+            name            : "JSON".to_string(),
+            extensions      : strings(&["json"]),
+            keywords        : strings(&["true", "false", "null"]),
+            strings         : strings(&["\""]),
+            numbers         : NumberFormat { exponent: true, ..Default::default() },
+            brackets        : pairs(&[("{", "}"), ("[", "]")]),
+            key_separators  : strings(&[":"]),
+            ..Default::default()
+        }
+    }
+
+    /// Lua, https://www.lua.org
+    pub fn lua() -> Self {
+        Self {
+            name            : "Lua".to_string(),
+            extensions      : strings(&["lua"]),
+            keywords        : strings(&[
+                "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto",
+                "if", "in", "local", "nil", "not", "or", "repeat", "return", "then", "true",
+                "until", "while",
+            ]),
+            builtins        : strings(&[
+                "assert", "error", "getmetatable", "ipairs", "next", "pairs", "pcall", "print",
+                "require", "select", "setmetatable", "tonumber", "tostring", "type", "xpcall",
+                "coroutine", "io", "math", "os", "string", "table", "self",
+            ]),
+            line_comments   : strings(&["--"]),
+            block_comments  : pairs(&[("--[[", "]]")]),
+            strings         : strings(&["\"", "'"]),
+            raw_strings     : vec![
+                RawString { comment: true, ..RawString::new("--[", Some('='), "[", "]", "]", true) },
+                RawString::new("[", Some('='), "[", "]", "]", true),
+            ],
+            numbers         : NumberFormat { hex: true, exponent: true, ..Default::default() },
+            operators       : strings(&["==", "~=", "<=", ">=", "..", "...", "//", "::", "<<", ">>"]),
+            brackets        : pairs(&[("(", ")"), ("{", "}"), ("[", "]")]),
+            ..Default::default()
+        }
+    }
+
+    /// Python, https://www.python.org
+    pub fn python() -> Self {
+        Self {
+            name            : "Python".to_string(),
+            extensions      : strings(&["py", "pyw"]),
+            keywords        : strings(&[
+                "False", "None", "True", "and", "as", "assert", "async", "await", "break", "case",
+                "class", "continue", "def", "del", "elif", "else", "except", "finally", "for",
+                "from", "global", "if", "import", "in", "is", "lambda", "match", "nonlocal", "not",
+                "or", "pass", "raise", "return", "try", "while", "with", "yield",
+            ]),
+            builtins        : strings(&[
+                "abs", "all", "any", "bool", "dict", "enumerate", "filter", "float", "int",
+                "isinstance", "len", "list", "map", "max", "min", "open", "print", "range", "repr",
+                "self", "set", "sorted", "str", "sum", "super", "tuple", "type", "zip",
+            ]),
+            line_comments   : strings(&["#"]),
+            strings         : strings(&["\"\"\"", "'''", "\"", "'"]),
+            multiline_strings: strings(&["\"\"\"", "'''"]),
+            string_prefixes : strings(&["rb", "br", "fr", "rf", "r", "b", "f", "u"]),
+            numbers         : NumberFormat { hex: true, binary: true, octal: true, exponent: true, underscores: true },
+            operators       : strings(&[
+                "==", "!=", "<=", ">=", "**", "//", "->", ":=", "+=", "-=", "*=", "/=", "%=",
+                "**=", "//=", "<<", ">>",
+            ]),
+            brackets        : pairs(&[("(", ")"), ("{", "}"), ("[", "]")]),
+            attributes      : pairs(&[("@", "")]),
+            ..Default::default()
+        }
+    }
+
+    /// The OpenGL Shading Language
+    pub fn glsl() -> Self {
+        Self {
+            name            : "GLSL".to_string(),
+            extensions      : strings(&["glsl", "vert", "frag", "geom", "comp", "tesc", "tese"]),
+            keywords        : strings(&[
+                "attribute", "break", "buffer", "case", "centroid", "const", "continue", "default",
+                "discard", "do", "else", "false", "flat", "for", "highp", "if", "in", "inout",
+                "invariant", "layout", "lowp", "mediump", "noperspective", "out", "precision",
+                "return", "shared", "smooth", "struct", "switch", "true", "uniform", "varying",
+                "while",
+            ]),
+            builtins        : strings(&[
+                "void", "bool", "int", "uint", "float", "double", "vec2", "vec3", "vec4", "ivec2",
+                "ivec3", "ivec4", "uvec2", "uvec3", "uvec4", "bvec2", "bvec3", "bvec4", "mat2",
+                "mat3", "mat4", "sampler2D", "sampler3D", "samplerCube", "abs", "clamp", "cos",
+                "cross", "dot", "floor", "fract", "length", "max", "min", "mix", "mod", "normalize",
+                "pow", "reflect", "sin", "smoothstep", "sqrt", "step", "texture",
+            ]),
+            line_comments   : strings(&["//"]),
+            block_comments  : pairs(&[("/*", "*/")]),
+            numbers         : NumberFormat { hex: true, exponent: true, ..Default::default() },
+            operators       : strings(&[
+                "==", "!=", "<=", ">=", "&&", "||", "^^", "++", "--", "+=", "-=", "*=", "/=",
+                "<<", ">>",
+            ]),
+            brackets        : pairs(&[("(", ")"), ("{", "}"), ("[", "]")]),
+            attributes      : pairs(&[("#", "")]),
+            ..Default::default()
+        }
+    }
+
+    /// The WebGPU Shading Language
+    pub fn wgsl() -> Self {
+        Self {
+            name            : "WGSL".to_string(),
+            extensions      : strings(&["wgsl"]),
+            keywords        : strings(&[
+                "alias", "break", "case", "const", "const_assert", "continue", "continuing",
+                "default", "diagnostic", "discard", "else", "enable", "false", "fn", "for", "if",
+                "let", "loop", "override", "requires", "return", "struct", "switch", "true", "var",
+                "while",
+            ]),
+            builtins        : strings(&[
+                "bool", "f16", "f32", "i32", "u32", "vec2", "vec3", "vec4", "vec2f", "vec3f",
+                "vec4f", "vec2i", "vec3i", "vec4i", "vec2u", "vec3u", "vec4u", "mat2x2", "mat3x3",
+                "mat4x4", "mat4x4f", "array", "atomic", "ptr", "sampler", "texture_2d",
+                "texture_storage_2d", "function", "private", "workgroup", "uniform", "storage",
+            ]),
+            line_comments   : strings(&["//"]),
+            block_comments  : pairs(&[("/*", "*/")]),
+            nested_comments : true,
+            numbers         : NumberFormat { hex: true, exponent: true, ..Default::default() },
+            operators       : strings(&[
+                "==", "!=", "<=", ">=", "&&", "||", "->", "++", "--", "+=", "-=", "*=", "/=",
+                "<<", ">>",
+            ]),
+            brackets        : pairs(&[("(", ")"), ("{", "}"), ("[", "]")]),
+            attributes      : pairs(&[("@", "")]),
+            ..Default::default()
+        }
+    }
+
+    /// Markdown, https://commonmark.org
+    pub fn markdown() -> Self {
+        Self {
+            name            : "Markdown".to_string(),
+            extensions      : strings(&["md", "markdown"]),
+            escape          : None,
+            spans           : vec![
+                DelimitedSpan { multiline: true, ..DelimitedSpan::new("```", Some("```"), TokenType::String, true) },
+                DelimitedSpan::new("#", Some(""), TokenType::Heading, true),
+                DelimitedSpan::new(">", Some(""), TokenType::Comment, true),
+                DelimitedSpan::new("- ", None, TokenType::Operator, true),
+                DelimitedSpan::new("* ", None, TokenType::Operator, true),
+                DelimitedSpan::new("+ ", None, TokenType::Operator, true),
+                DelimitedSpan::new("**", Some("**"), TokenType::Emphasis, false),
+                DelimitedSpan::new("__", Some("__"), TokenType::Emphasis, false),
+                DelimitedSpan::new("*", Some("*"), TokenType::Emphasis, false),
+                DelimitedSpan::new("`", Some("`"), TokenType::String, false),
+                DelimitedSpan::new("[", Some("]"), TokenType::Link, false),
+                DelimitedSpan::new("<http", Some(">"), TokenType::Link, false),
+            ],
+            markup          : true,
+            ..Default::default()
+        }
+    }
+}
+
+/// A registered language, the definition together with its lookup tables.
+pub struct Language {
+    pub definition          : LanguageDefinition,
+
+    keywords                : HashSet<String>,
+    builtins                : HashSet<String>,
+}
+
+impl Language {
+
+    pub fn new(definition: LanguageDefinition) -> Self {
+        let keywords = definition.keywords.iter().cloned().collect();
+        let builtins = definition.builtins.iter().cloned().collect();
+
+        Self {
+            definition,
+            keywords,
+            builtins,
+        }
+    }
+
+    /// Returns the token type of an identifier like word
+    pub fn word_type(&self, word: &str) -> TokenType {
+        if self.keywords.contains(word) {
+            TokenType::Keyword
+        } else if self.builtins.contains(word) {
+            TokenType::Builtin
+        } else {
+            TokenType::Identifier
+        }
+    }
+
+    /// Returns true if the char can be part of an identifier
+    pub fn is_identifier_char(&self, c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || self.definition.identifier_chars.contains(c)
+    }
+}
+
+/// All languages known to an editor, the built-in languages are registered first.
+pub struct LanguageRegistry {
+    languages               : Vec<Language>,
+}
+
+impl Default for LanguageRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LanguageRegistry {
+
+    pub fn new() -> Self {
+        // The order has to match the CodeEditorMode constants
+        Self {
+            languages       : vec![
+                Language::new(LanguageDefinition::rhai()),
+                Language::new(LanguageDefinition::text()),
+                Language::new(LanguageDefinition::settings()),
+                Language::new(LanguageDefinition::rust()),
+                Language::new(LanguageDefinition::toml()),
+                Language::new(LanguageDefinition::json()),
+                Language::new(LanguageDefinition::lua()),
+                Language::new(LanguageDefinition::python()),
+                Language::new(LanguageDefinition::glsl()),
+                Language::new(LanguageDefinition::wgsl()),
+                Language::new(LanguageDefinition::markdown()),
+            ],
+        }
+    }
+
+    /// Registers the language and returns its handle. A language with the same name is replaced.
+    pub fn register(&mut self, definition: LanguageDefinition) -> CodeEditorMode {
+        if let Some(mode) = self.find(definition.name.as_str()) {
+            self.languages[mode.0] = Language::new(definition);
+            mode
+        } else {
+            self.languages.push(Language::new(definition));
+            CodeEditorMode(self.languages.len() - 1)
+        }
+    }
+
+    /// Returns the language of the handle, unknown handles return the Text language
+    pub fn get(&self, mode: CodeEditorMode) -> &Language {
+        if let Some(language) = self.languages.get(mode.0) {
+            language
+        } else {
+            &self.languages[CodeEditorMode::Text.0]
+        }
+    }
+
+    /// Finds a language by name, the comparison ignores case
+    pub fn find(&self, name: &str) -> Option<CodeEditorMode> {
+        self.languages.iter().position(|l| l.definition.name.eq_ignore_ascii_case(name)).map(CodeEditorMode)
+    }
+
+    /// Finds a language by file extension
+    pub fn find_by_extension(&self, extension: &str) -> Option<CodeEditorMode> {
+        let extension = extension.trim_start_matches('.');
+        self.languages.iter().position(|l| l.definition.extensions.iter().any(|e| e.eq_ignore_ascii_case(extension))).map(CodeEditorMode)
+    }
+}
@@ -1,5 +1,10 @@
 mod scanner;
 mod draw2d;
+pub mod textbuffer;
+pub mod selection;
+pub mod search;
+pub mod highlighter;
+pub mod language;
 pub mod theme;
 pub mod settings;
 pub mod codeeditor;
@@ -16,7 +21,11 @@ pub enum WidgetKey {
     Down,
     Left,
     Space,
-    Tab
+    Tab,
+    Home,
+    End,
+    PageUp,
+    PageDown,
 }
 
 pub mod prelude {
@@ -26,5 +35,11 @@ pub mod prelude {
     pub use crate::WidgetKey;
     pub use crate::draw2d::*;
     pub use crate::codeeditor::*;
+    pub use crate::textbuffer::*;
+    pub use crate::selection::*;
+    pub use crate::search::*;
+    pub use crate::highlighter::*;
+    pub use crate::language::*;
     pub use crate::error::*;
+    pub use crate::undo::*;
 }
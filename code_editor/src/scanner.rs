@@ -1,68 +1,81 @@
 // Based on https://github.com/ceronman/loxido/blob/master/src/scanner.rs
 // Licensed under the MIT license of Manuel Cerón.
 
-use std::collections::HashMap;
+use serde::{ Deserialize, Serialize };
 
+use crate::prelude::*;
+
+/// The token types are language independent, what produces a token is described by the
+/// LanguageDefinition of the current mode.
 #[allow(dead_code)]
-#[derive(Eq, PartialEq, Debug, Copy, Clone, Hash)]
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Copy, Clone, Hash)]
 pub enum TokenType {
-    LeftParen,
-    RightParen,
-    LeftBrace,
-    RightBrace,
-    Comma,
-    Dot,
-    Minus,
-    Plus,
-    Semicolon,
-    Slash,
-    Star,
-    Dollar,
-
     LineFeed,
     Space,
-    Quotation,
+    Text,
     Unknown,
-    SingeLineComment,
-    HexColor,
-
-    // One or two character tokens.
-    Bang,
-    BangEqual,
-    Equal,
-    EqualEqual,
-    Greater,
-    GreaterEqual,
-    Less,
-    LessEqual,
 
     // Literals.
     Identifier,
     String,
+    Quotation,
     Number,
+    HexColor,
 
-    // Keywords.
-    And,
-    Class,
-    Else,
-    False,
-    For,
-    Fun,
-    If,
-    Nil,
-    Or,
-    Print,
-    Return,
-    Super,
-    This,
-    True,
-    Let,
-    While,
+    // Words listed by the language.
+    Keyword,
+    Builtin,
+
+    Attribute,
+    /// The key of a key / value pair
+    Key,
+
+    // Markup.
+    Heading,
+    Emphasis,
+    Link,
+
+    Comment,
+    DocComment,
+    BlockComment,
+    /// A string which continues on the next line
+    MultilineString,
+
+    Bracket,
+    /// The start and end of an expression inside a template string
+    Interpolation,
+    Operator,
 
     Error,
     Eof,
 }
 
+/// The state of the scanner at the start of a line. Constructs which continue on the next line
+/// are tracked here so that a line can be scanned without scanning the lines above it.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Default)]
+pub enum LineState {
+    #[default]
+    Normal,
+    /// Inside a block comment, index is the comment pair inside the block or doc block comments
+    BlockComment { index: u8, depth: u8, doc: bool },
+    /// Inside a string, index is the string delimiter
+    String { index: u8 },
+    /// Inside a raw string with the given padding count
+    RawString { index: u8, padding: u8 },
+    /// Inside a delimited span which continues on the following lines
+    Span { index: u8 },
+    /// Inside a template string, or inside an interpolated expression of it
+    Template { index: u8, in_expression: bool, depth: u8 },
+}
+
+/// A token inside a single line, start and end are byte offsets into the line.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct TokenSpan {
+    pub kind: TokenType,
+    pub start: usize,
+    pub end: usize,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Token<'sourcecode> {
     pub kind: TokenType,
@@ -81,93 +94,278 @@ impl<'sourcecode> Token<'sourcecode> {
     }
 }
 
+/// A template string which is currently open, templates can be nested inside interpolations.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+struct Template {
+    /// The index of the delimiter in the language definition
+    delimiter: usize,
+    /// True while scanning an interpolated expression
+    in_expression: bool,
+    /// The open braces inside the interpolated expression
+    depth: usize,
+}
+
 #[allow(dead_code)]
 pub struct Scanner<'sourcecode> {
-    keywords: HashMap<&'static str, TokenType>,
+    language: &'sourcecode Language,
     code: &'sourcecode str,
     start: usize,
     current: usize,
     line: usize,
+    state: LineState,
+    templates: Vec<Template>,
 }
 
 #[allow(dead_code)]
 impl<'sourcecode> Scanner<'sourcecode> {
-    pub fn new(code: &'sourcecode str) -> Scanner {
-        let mut keywords = HashMap::with_capacity(16);
-        keywords.insert("and", TokenType::And);
-        keywords.insert("class", TokenType::Class);
-        keywords.insert("else", TokenType::Else);
-        keywords.insert("false", TokenType::False);
-        keywords.insert("for", TokenType::For);
-        keywords.insert("fn", TokenType::Fun);
-        keywords.insert("if", TokenType::If);
-        keywords.insert("nil", TokenType::Nil);
-        keywords.insert("or", TokenType::Or);
-        keywords.insert("print", TokenType::Print);
-        keywords.insert("return", TokenType::Return);
-        keywords.insert("super", TokenType::Super);
-        keywords.insert("this", TokenType::This);
-        keywords.insert("true", TokenType::True);
-        keywords.insert("let", TokenType::Let);
-        keywords.insert("while", TokenType::While);
-
-        Scanner {
-            keywords,
+    pub fn new(code: &'sourcecode str, language: &'sourcecode Language) -> Scanner<'sourcecode> {
+        Scanner::with_state(code, language, LineState::Normal)
+    }
+
+    /// Creates a scanner which continues scanning in the given state
+    pub fn with_state(code: &'sourcecode str, language: &'sourcecode Language, state: LineState) -> Scanner<'sourcecode> {
+        let mut scanner = Scanner {
+            language,
             code,
             start: 0,
             current: 0,
             line: 1,
+            state: LineState::Normal,
+            templates: vec![],
+        };
+
+        // States which do not fit the language are ignored
+        let definition = &language.definition;
+        match state {
+            LineState::Normal => {},
+            LineState::BlockComment { index, doc, .. } => {
+                let pairs = if doc { &definition.doc_block_comments } else { &definition.block_comments };
+                if (index as usize) < pairs.len() {
+                    scanner.state = state;
+                }
+            },
+            LineState::String { index } => {
+                if (index as usize) < definition.strings.len() {
+                    scanner.state = state;
+                }
+            },
+            LineState::RawString { index, .. } => {
+                if (index as usize) < definition.raw_strings.len() {
+                    scanner.state = state;
+                }
+            },
+            LineState::Span { index } => {
+                if (index as usize) < definition.spans.len() {
+                    scanner.state = state;
+                }
+            },
+            LineState::Template { index, in_expression, depth } => {
+                if (index as usize) < definition.templates.len() {
+                    scanner.templates.push(Template { delimiter: index as usize, in_expression, depth: depth as usize });
+                }
+            },
+        }
+        scanner
+    }
+
+    /// The current state, after scanning a line this is the state at the start of the next line
+    pub fn state(&self) -> LineState {
+        if let Some(template) = self.templates.last() {
+            LineState::Template {
+                index: template.delimiter as u8,
+                in_expression: template.in_expression,
+                depth: template.depth.min(u8::MAX as usize) as u8,
+            }
+        } else {
+            self.state
         }
     }
 
+    /// Scans the whole code and returns the token spans, Eof is not included
+    pub fn scan_spans(&mut self) -> Vec<TokenSpan> {
+        let mut spans = vec![];
+        loop {
+            let token = self.scan_token();
+            if token.kind == TokenType::Eof {
+                break;
+            }
+            spans.push(TokenSpan {
+                kind: token.kind,
+                start: self.start,
+                end: self.current,
+            });
+        }
+        spans
+    }
+
     pub fn scan_token(&mut self) -> Token<'sourcecode> {
-        //self.skip_whitespace();
         self.start = self.current;
         if self.is_at_end() {
             return self.make_token(TokenType::Eof);
         }
 
-        match self.advance() {
-            b' ' => self.make_token(TokenType::Space),
-            b'\n' => self.make_token(TokenType::LineFeed),
-            b'(' => self.make_token(TokenType::LeftParen),
-            b')' => self.make_token(TokenType::RightParen),
-            b'{' => self.make_token(TokenType::LeftBrace),
-            b'}' => self.make_token(TokenType::RightBrace),
-            b'$' => self.make_token(TokenType::Dollar),
-            b';' => self.make_token(TokenType::Semicolon),
-            b',' => self.make_token(TokenType::Comma),
-            b'.' => self.make_token(TokenType::Dot),
-            b'-' => self.make_token(TokenType::Minus),
-            b'+' => self.make_token(TokenType::Plus),
-            b'/' if self.matches(b'/') => self.single_line_comment(),
-            b'#' => self.hex_color(),
-            b'/' => self.make_token(TokenType::Slash),
-            b'*' => self.make_token(TokenType::Star),
-            b'!' if self.matches(b'=') => self.make_token(TokenType::BangEqual),
-            b'!' => self.make_token(TokenType::Bang),
-            b'=' if self.matches(b'=') => self.make_token(TokenType::EqualEqual),
-            b'=' => self.make_token(TokenType::Equal),
-            b'<' if self.matches(b'=') => self.make_token(TokenType::LessEqual),
-            b'<' => self.make_token(TokenType::Less),
-            b'>' if self.matches(b'=') => self.make_token(TokenType::GreaterEqual),
-            b'>' => self.make_token(TokenType::Greater),
-            b'"' => self.string(),
-            b'`' => self.string2(),
-            c if is_digit(c) => self.number(),
-            c if is_alpha(c) => self.identifier(),
-            _ => self.make_token(TokenType::Unknown),//self.error_token("Unexpected character."),
+        let definition = &self.language.definition;
+
+        if !definition.highlight {
+            self.current = self.code.len();
+            return self.make_token(TokenType::Text);
+        }
+
+        if self.peek() == b'\n' {
+            self.advance();
+            self.line += 1;
+            return self.make_token(TokenType::LineFeed);
+        }
+
+        // Continue the construct which was open at the end of the previous line
+        match self.state {
+            LineState::BlockComment { index, depth, doc } => return self.block_comment_body(index as usize, depth as usize, doc),
+            LineState::String { index } => {
+                self.string_content(index as usize);
+                return self.make_token(TokenType::MultilineString);
+            },
+            LineState::RawString { index, padding } => {
+                self.raw_string_content(index as usize, padding as usize);
+                if definition.raw_strings[index as usize].comment {
+                    return self.make_token(TokenType::BlockComment);
+                }
+                return self.make_token(TokenType::MultilineString);
+            },
+            LineState::Span { index } => {
+                self.span_content(index as usize);
+                return self.make_token(definition.spans[index as usize].kind);
+            },
+            LineState::Normal | LineState::Template { .. } => {},
+        }
+
+        if let Some(template) = self.templates.last() {
+            if !template.in_expression {
+                return self.template_text();
+            }
+        }
+
+        if matches!(self.peek(), b' ' | b'\t' | b'\r') {
+            self.advance();
+            return self.make_token(TokenType::Space);
+        }
+
+        if let Some(token) = self.interpolated_brace() {
+            return token;
+        }
+
+        if definition.doc_comments.iter().any(|prefix| self.rest().starts_with(prefix.as_str())) {
+            return self.single_line_comment(TokenType::DocComment);
+        }
+
+        // An empty block comment like /**/ is not a doc comment
+        let empty_comment = definition.block_comments.iter().any(|(open, close)| self.rest().starts_with(format!("{}{}", open, close).as_str()));
+
+        if let Some(index) = definition.doc_block_comments.iter().position(|(open, _)| self.rest().starts_with(open.as_str())) {
+            if !empty_comment {
+                return self.block_comment(index, true);
+            }
+        }
+
+        if let Some(index) = definition.block_comments.iter().position(|(open, _)| self.rest().starts_with(open.as_str())) {
+            return self.block_comment(index, false);
+        }
+
+        // Comments like --[==[ ... ]==] in Lua, before the line comments they start with
+        if let Some(token) = self.raw_string(true) {
+            return token;
+        }
+
+        if definition.line_comments.iter().any(|prefix| self.rest().starts_with(prefix.as_str())) {
+            return self.single_line_comment(TokenType::Comment);
+        }
+
+        if let Some(token) = self.delimited_span() {
+            return token;
+        }
+
+        if let Some(token) = self.attribute() {
+            return token;
+        }
+
+        if let Some(token) = self.raw_string(false) {
+            return token;
+        }
+
+        let prefix = definition.string_prefixes.iter()
+            .filter(|prefix| self.rest().starts_with(prefix.as_str()))
+            .map(|prefix| prefix.len())
+            .find(|len| definition.strings.iter().any(|delimiter| self.rest()[*len..].starts_with(delimiter.as_str())))
+            .unwrap_or(0);
+
+        if let Some(index) = definition.strings.iter().position(|delimiter| self.rest()[prefix..].starts_with(delimiter.as_str())) {
+            self.current += prefix;
+            let token = self.string(index);
+            return self.key_or(token);
+        }
+
+        if let Some(delimiter) = definition.templates.iter().position(|delimiter| self.rest().starts_with(delimiter.as_str())) {
+            self.current += definition.templates[delimiter].len();
+            self.templates.push(Template { delimiter, in_expression: false, depth: 0 });
+            return self.template_text();
+        }
+
+        let c = self.peek_char();
+
+        if c == '\'' && definition.chars {
+            if let Some(token) = self.char_literal() {
+                return token;
+            }
+        }
+
+        if c == '#' && definition.hex_colors && self.peek_next().is_ascii_hexdigit() {
+            return self.hex_color();
+        }
+
+        if definition.markup {
+            return self.markup_text();
+        }
+
+        if c.is_ascii_digit() {
+            return self.number();
+        }
+
+        if c.is_alphabetic() || c == '_' || definition.identifier_chars.contains(c) {
+            let token = self.identifier();
+            return self.key_or(token);
+        }
+
+        if let Some(bracket) = definition.brackets.iter().flat_map(|(open, close)| [open, close]).find(|b| self.rest().starts_with(b.as_str())) {
+            self.current += bracket.len();
+            return self.make_token(TokenType::Bracket);
+        }
+
+        // The longest operator wins
+        if let Some(operator) = definition.operators.iter().filter(|op| self.rest().starts_with(op.as_str())).max_by_key(|op| op.len()) {
+            self.current += operator.len();
+            return self.make_token(TokenType::Operator);
+        }
+
+        self.advance_char();
+        if c.is_ascii_punctuation() {
+            self.make_token(TokenType::Operator)
+        } else {
+            self.make_token(TokenType::Unknown)//self.error_token("Unexpected character."),
         }
     }
 
     fn is_at_end(&self) -> bool {
-        self.current == self.code.len()
+        self.current >= self.code.len()
     }
 
     fn lexeme(&self) -> &'sourcecode str {
         &self.code[self.start..self.current]
     }
 
+    /// The not yet scanned code
+    fn rest(&self) -> &'sourcecode str {
+        &self.code[self.current..]
+    }
+
     fn make_token(&self, kind: TokenType) -> Token<'sourcecode> {
         Token {
             kind,
@@ -183,14 +381,19 @@ impl<'sourcecode> Scanner<'sourcecode> {
             self.code.as_bytes()[self.current]
         }
     }
+
     fn peek_next(&self) -> u8 {
-        if self.current > self.code.len() - 2 {
+        if self.current + 1 >= self.code.len() {
             b'\0'
         } else {
             self.code.as_bytes()[self.current + 1]
         }
     }
 
+    fn peek_char(&self) -> char {
+        self.rest().chars().next().unwrap_or('\0')
+    }
+
     fn error_token(&self, message: &'static str) -> Token<'static> {
         Token {
             kind: TokenType::Error,
@@ -205,6 +408,13 @@ impl<'sourcecode> Scanner<'sourcecode> {
         char
     }
 
+    /// Advances over the next char which may be more than one byte long
+    fn advance_char(&mut self) -> char {
+        let c = self.peek_char();
+        self.current += c.len_utf8().min(self.code.len() - self.current);
+        c
+    }
+
     fn matches(&mut self, expected: u8) -> bool {
         if self.is_at_end() || self.peek() != expected {
             false
@@ -214,118 +424,403 @@ impl<'sourcecode> Scanner<'sourcecode> {
         }
     }
 
-    fn skip_whitespace(&mut self) {
-        while !self.is_at_end() {
-            match self.peek() {
-                b' ' | b'\r' | b'\t' => {
-                    self.advance();
-                }
-                b'\n' => {
-                    self.line += 1;
-                    self.advance();
-                }
-                b'/' if self.peek_next() == b'/' => {
-                    while self.peek() != b'\n' && !self.is_at_end() {
-                        self.advance();
-                    }
-                }
-                _ => return,
-            }
-        }
-    }
-
-    fn string(&mut self) -> Token<'sourcecode> {
+    fn string(&mut self, index: usize) -> Token<'sourcecode> {
+        self.current += self.language.definition.strings[index].len();
         let b_current = self.current;
 
-        while self.peek() != b'"' && !self.is_at_end() {
-            if self.peek() == b'\n' {
-                //self.line += 1;
-                self.current = b_current;
-                return self.make_token(TokenType::Quotation);
-            }
-            self.advance();
+        if self.string_content(index) {
+            return self.make_token(TokenType::String);
         }
 
-        if self.is_at_end() {
-            //self.error_token("Unterminated string.")
-            self.current = b_current;
-            self.make_token(TokenType::Quotation)
-        } else {
-            self.advance();
-            self.make_token(TokenType::String)
+        if self.state != LineState::Normal {
+            return self.make_token(TokenType::MultilineString);
         }
+
+        // Unterminated string, only the delimiter is a token
+        self.current = b_current;
+        self.make_token(TokenType::Quotation)
     }
 
-    fn string2(&mut self) -> Token<'sourcecode> {
-        let b_current = self.current;
+    /// Scans the string up to and including its delimiter, returns false if the line ends first.
+    /// Multi-line strings and lines which end with the escape char continue on the next line.
+    fn string_content(&mut self, index: usize) -> bool {
+        let definition = &self.language.definition;
+        let delimiter = definition.strings[index].as_str();
+        let escape = definition.escape;
+
+        self.state = LineState::Normal;
 
-        while self.peek() != b'`' && !self.is_at_end() {
-            if self.peek() == b'\n' {
-                //self.line += 1;
-                self.current = b_current;
-                return self.make_token(TokenType::Quotation);
+        while !self.is_at_end() && self.peek() != b'\n' {
+            if self.rest().starts_with(delimiter) {
+                self.current += delimiter.len();
+                return true;
+            }
+            if Some(self.advance_char()) == escape {
+                if self.rest().trim_start_matches('\r').is_empty() || self.rest().trim_start_matches('\r').starts_with('\n') {
+                    self.state = LineState::String { index: index as u8 };
+                    return false;
+                }
+                self.advance_char();
             }
-            self.advance();
         }
 
-        if self.is_at_end() {
-            //self.error_token("Unterminated string.")
-            self.current = b_current;
-            self.make_token(TokenType::Quotation)
-        } else {
-            self.advance();
-            self.make_token(TokenType::String)
+        if definition.multiline_strings.iter().any(|d| d == delimiter) {
+            self.state = LineState::String { index: index as u8 };
         }
+        false
     }
 
     fn number(&mut self) -> Token<'sourcecode> {
+        let format = &self.language.definition.numbers;
+        let underscores = format.underscores;
+
+        let is_digit = |c: u8| c.is_ascii_digit() || (underscores && c == b'_');
+
+        if self.peek() == b'0' {
+            let prefix = self.peek_next().to_ascii_lowercase();
+            let radix_digit : Option<fn(u8) -> bool> = match prefix {
+                b'x' if format.hex => Some(|c: u8| c.is_ascii_hexdigit()),
+                b'b' if format.binary => Some(|c: u8| c == b'0' || c == b'1'),
+                b'o' if format.octal => Some(|c: u8| (b'0'..=b'7').contains(&c)),
+                _ => None,
+            };
+
+            if let Some(radix_digit) = radix_digit {
+                self.current += 2;
+                while radix_digit(self.peek()) || (underscores && self.peek() == b'_') {
+                    self.advance();
+                }
+                return self.make_token(TokenType::Number);
+            }
+        }
+
         while is_digit(self.peek()) {
             self.advance();
         }
 
-        if self.peek() == b'.' && is_digit(self.peek_next()) {
+        if self.peek() == b'.' && self.peek_next().is_ascii_digit() {
             self.advance();
             while is_digit(self.peek()) {
                 self.advance();
             }
         }
 
+        if format.exponent && (self.peek() == b'e' || self.peek() == b'E') {
+            let next = self.peek_next();
+            let sign = next == b'+' || next == b'-';
+            let digit_at = if sign { self.current + 2 } else { self.current + 1 };
+
+            if self.code.as_bytes().get(digit_at).is_some_and(|c| c.is_ascii_digit()) {
+                self.current = digit_at;
+                while is_digit(self.peek()) {
+                    self.advance();
+                }
+            }
+        }
+
         self.make_token(TokenType::Number)
     }
 
     fn hex_color(&mut self) -> Token<'sourcecode> {
-        while self.is_at_end() == false && self.peek() != b'\n' {
+        self.advance();
+        while self.peek().is_ascii_hexdigit() {
             self.advance();
         }
         self.make_token(TokenType::HexColor)
     }
 
-    fn single_line_comment(&mut self) -> Token<'sourcecode> {
-        while self.is_at_end() == false && self.peek() != b'\n' {
-            self.advance();
+    /// Scans 'a', '\n' or '\u{1F600}', returns None if the quote does not start a char literal
+    fn char_literal(&mut self) -> Option<Token<'sourcecode>> {
+        let mut chars = self.rest().char_indices().skip(1);
+
+        let end = match chars.next() {
+            Some((_, c)) if Some(c) == self.language.definition.escape => {
+                // Escape sequences are short, the closing quote has to follow soon
+                chars.take(10).take_while(|(_, c)| *c != '\n').find(|(_, c)| *c == '\'').map(|(i, _)| i)
+            },
+            Some((_, c)) if c != '\'' && c != '\n' => {
+                chars.next().filter(|(_, c)| *c == '\'').map(|(i, _)| i)
+            },
+            _ => None,
+        }?;
+
+        self.current += end + 1;
+        Some(self.make_token(TokenType::String))
+    }
+
+    /// The text of the innermost template string up to an interpolation or its end
+    fn template_text(&mut self) -> Token<'sourcecode> {
+        let definition = &self.language.definition;
+        let template = self.templates.len() - 1;
+        let delimiter = definition.templates[self.templates[template].delimiter].as_str();
+
+        while !self.is_at_end() && self.peek() != b'\n' {
+            if self.rest().starts_with(delimiter) {
+                self.current += delimiter.len();
+                self.templates.pop();
+                return self.make_token(TokenType::String);
+            }
+            if let Some((open, _)) = &definition.interpolation {
+                if self.rest().starts_with(open.as_str()) {
+                    if self.current == self.start {
+                        self.current += open.len();
+                        self.templates[template].in_expression = true;
+                        return self.make_token(TokenType::Interpolation);
+                    }
+                    return self.make_token(TokenType::String);
+                }
+            }
+            if Some(self.advance_char()) == definition.escape && !self.is_at_end() && self.peek() != b'\n' {
+                self.advance_char();
+            }
         }
-        self.make_token(TokenType::SingeLineComment)
+
+        // The template continues on the next line
+        self.make_token(TokenType::String)
     }
 
-    fn identifier(&mut self) -> Token<'sourcecode> {
-        while is_alpha(self.peek()) || is_digit(self.peek()) {
+    /// Tracks the braces inside an interpolated expression, the closing brace of the
+    /// interpolation continues the template string
+    fn interpolated_brace(&mut self) -> Option<Token<'sourcecode>> {
+        let language = self.language;
+        let (_, close) = language.definition.interpolation.as_ref()?;
+        let template = self.templates.last_mut()?;
+
+        if template.depth == 0 && self.code[self.current..].starts_with(close.as_str()) {
+            template.in_expression = false;
+            self.current += close.len();
+            return Some(self.make_token(TokenType::Interpolation));
+        }
+
+        match self.code.as_bytes()[self.current] {
+            b'{' => template.depth += 1,
+            b'}' => template.depth = template.depth.saturating_sub(1),
+            _ => {}
+        }
+        None
+    }
+
+    /// Returns a Key token if the token is followed by a key separator, otherwise the token
+    fn key_or(&self, token: Token<'sourcecode>) -> Token<'sourcecode> {
+        let separators = &self.language.definition.key_separators;
+        let rest = self.rest().trim_start_matches([' ', '\t']);
+
+        if token.kind != TokenType::Quotation && separators.iter().any(|separator| rest.starts_with(separator.as_str())) {
+            self.make_token(TokenType::Key)
+        } else {
+            token
+        }
+    }
+
+    /// True if only whitespace precedes the current position on its line
+    fn at_line_start(&self) -> bool {
+        let before = &self.code[..self.current];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        before[line_start..].trim().is_empty()
+    }
+
+    fn delimited_span(&mut self) -> Option<Token<'sourcecode>> {
+        let language = self.language;
+        let line_end = self.rest().find('\n').map_or(self.code.len(), |i| self.current + i);
+
+        for (index, span) in language.definition.spans.iter().enumerate() {
+            if !self.rest().starts_with(span.open.as_str()) || (span.line_start && !self.at_line_start()) {
+                continue;
+            }
+
+            let after_open = self.current + span.open.len();
+            let end = match &span.close {
+                None => Some(after_open),
+                Some(close) if close.is_empty() => Some(line_end),
+                Some(close) => self.code[after_open..line_end].find(close.as_str()).map(|i| after_open + i + close.len()),
+            };
+
+            if let Some(end) = end {
+                self.current = end;
+                return Some(self.make_token(span.kind));
+            }
+
+            if span.multiline {
+                self.current = after_open;
+                self.span_content(index);
+                return Some(self.make_token(span.kind));
+            }
+        }
+        None
+    }
+
+    /// Scans the multiline span up to and including its close text, or up to the line end
+    fn span_content(&mut self, index: usize) {
+        let close = self.language.definition.spans[index].close.as_deref().unwrap_or("");
+
+        self.state = LineState::Normal;
+
+        let line_end = self.rest().find('\n').map_or(self.code.len(), |i| self.current + i);
+        if let Some(i) = self.code[self.current..line_end].find(close).filter(|_| !close.is_empty()) {
+            self.current += i + close.len();
+            return;
+        }
+
+        self.current = line_end;
+        self.state = LineState::Span { index: index as u8 };
+    }
+
+    /// Scans #[derive(Debug)] style attributes and @name / #name annotations
+    fn attribute(&mut self) -> Option<Token<'sourcecode>> {
+        let language = self.language;
+        let (open, close) = language.definition.attributes.iter().find(|(open, _)| self.rest().starts_with(open.as_str()))?;
+        let after_open = self.current + open.len();
+
+        if close.is_empty() {
+            let name = self.code[after_open..].chars().take_while(|c| language.is_identifier_char(*c)).map(|c| c.len_utf8()).sum::<usize>();
+            if name == 0 {
+                return None;
+            }
+            self.current = after_open + name;
+            return Some(self.make_token(TokenType::Attribute));
+        }
+
+        // The last char of the open text nests, e.g. the brackets in #[cfg(any[a, b])]
+        let nest = open.chars().last();
+        let mut depth = 1;
+
+        self.current = after_open;
+        while !self.is_at_end() && self.peek() != b'\n' {
+            if self.rest().starts_with(close.as_str()) {
+                self.current += close.len();
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            } else if Some(self.advance_char()) == nest {
+                depth += 1;
+            }
+        }
+        Some(self.make_token(TokenType::Attribute))
+    }
+
+    /// Scans a raw string, or a raw comment if comment is true
+    fn raw_string(&mut self, comment: bool) -> Option<Token<'sourcecode>> {
+        let language = self.language;
+
+        for (index, raw) in language.definition.raw_strings.iter().enumerate() {
+            if raw.comment != comment || !self.rest().starts_with(raw.prefix.as_str()) {
+                continue;
+            }
+
+            let mut at = self.current + raw.prefix.len();
+            let mut padding = 0;
+            if let Some(p) = raw.padding {
+                while self.code[at..].starts_with(p) {
+                    at += p.len_utf8();
+                    padding += 1;
+                }
+            }
+
+            if !self.code[at..].starts_with(raw.open.as_str()) {
+                continue;
+            }
+
+            self.current = at + raw.open.len();
+            if comment {
+                self.raw_string_content(index, padding);
+                return Some(self.make_token(TokenType::BlockComment));
+            }
+            if self.raw_string_content(index, padding) || self.state == LineState::Normal {
+                return Some(self.make_token(TokenType::String));
+            }
+            return Some(self.make_token(TokenType::MultilineString));
+        }
+        None
+    }
+
+    /// Scans the raw string up to and including its end, returns false if the line ends first
+    fn raw_string_content(&mut self, index: usize, padding: usize) -> bool {
+        let raw = &self.language.definition.raw_strings[index];
+
+        let mut end = raw.close.clone();
+        if let Some(p) = raw.padding {
+            end.push_str(p.to_string().repeat(padding).as_str());
+        }
+        end.push_str(raw.suffix.as_str());
+
+        self.state = LineState::Normal;
+
+        let line_end = self.rest().find('\n').map_or(self.code.len(), |i| self.current + i);
+        if let Some(i) = self.code[self.current..line_end].find(end.as_str()) {
+            self.current += i + end.len();
+            return true;
+        }
+
+        self.current = line_end;
+        if raw.multiline {
+            self.state = LineState::RawString { index: index as u8, padding: padding.min(u8::MAX as usize) as u8 };
+        }
+        false
+    }
+
+    /// A word, a number or a single char of prose
+    fn markup_text(&mut self) -> Token<'sourcecode> {
+        if self.advance_char().is_alphanumeric() {
+            while self.peek_char().is_alphanumeric() {
+                self.advance_char();
+            }
+        }
+        self.make_token(TokenType::Text)
+    }
+
+    fn single_line_comment(&mut self, kind: TokenType) -> Token<'sourcecode> {
+        while !self.is_at_end() && self.peek() != b'\n' {
             self.advance();
         }
-        self.make_token(self.identifier_type())
+        self.make_token(kind)
     }
 
-    fn identifier_type(&self) -> TokenType {
-        self.keywords
-            .get(self.lexeme())
-            .cloned()
-            .unwrap_or(TokenType::Identifier)
+    fn block_comment(&mut self, index: usize, doc: bool) -> Token<'sourcecode> {
+        let definition = &self.language.definition;
+        let pairs = if doc { &definition.doc_block_comments } else { &definition.block_comments };
+
+        self.current += pairs[index].0.len();
+        self.block_comment_body(index, 1, doc)
     }
-}
 
-fn is_digit(c: u8) -> bool {
-    c.is_ascii_digit()
-}
+    /// Scans the comment up to its end or the end of the line, depth is the count of open comments
+    fn block_comment_body(&mut self, index: usize, mut depth: usize, doc: bool) -> Token<'sourcecode> {
+        let definition = &self.language.definition;
+        let close = if doc { &definition.doc_block_comments[index].1 } else { &definition.block_comments[index].1 };
+
+        self.state = LineState::Normal;
 
-fn is_alpha(c: u8) -> bool {
-    c.is_ascii_alphabetic() || c == b'_'
-}
\ No newline at end of file
+        while !self.is_at_end() && self.peek() != b'\n' {
+            if self.rest().starts_with(close.as_str()) {
+                self.current += close.len();
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            } else if let Some((open, _)) = definition.block_comments.iter().find(|(open, _)| definition.nested_comments && self.rest().starts_with(open.as_str())) {
+                self.current += open.len();
+                depth += 1;
+            } else {
+                self.advance_char();
+            }
+        }
+
+        if depth > 0 {
+            self.state = LineState::BlockComment { index: index as u8, depth: depth.min(u8::MAX as usize) as u8, doc };
+        }
+
+        if doc {
+            self.make_token(TokenType::DocComment)
+        } else {
+            self.make_token(TokenType::BlockComment)
+        }
+    }
+
+    fn identifier(&mut self) -> Token<'sourcecode> {
+        while !self.is_at_end() && self.language.is_identifier_char(self.peek_char()) {
+            self.advance_char();
+        }
+        self.make_token(self.language.word_type(self.lexeme()))
+    }
+}
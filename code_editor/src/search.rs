@@ -0,0 +1,166 @@
+use crate::prelude::*;
+
+use regex::{ Regex, RegexBuilder };
+
+/// How the query of a search is matched
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct SearchOptions {
+    pub case_sensitive      : bool,
+    /// Only match whole words, the chars before and after a match are no word chars
+    pub whole_word          : bool,
+    /// The query is a regular expression, replacements can use its captures as $1 or ${name}
+    pub regex               : bool,
+    /// Continue at the start of the text after the last match, and the other way round
+    pub wrap_around         : bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            case_sensitive  : false,
+            whole_word      : false,
+            regex           : false,
+            wrap_around     : true,
+        }
+    }
+}
+
+/// A compiled search query together with the matches in the text it was last run on.
+pub struct Search {
+    pub query               : String,
+    pub options             : SearchOptions,
+
+    regex                   : Regex,
+
+    /// The text of the last search, the buffer is reused when the text changed
+    text                    : String,
+    /// The (start, end) char indices of the matches and their byte offsets in the text
+    matches                 : Vec<(usize, usize)>,
+    byte_ranges             : Vec<(usize, usize)>,
+    /// The matches are up to date with the text
+    valid                   : bool,
+}
+
+impl Search {
+
+    /// Compiles the query, returns the error message of an invalid regular expression
+    pub fn new(query: &str, options: SearchOptions) -> Result<Self, String> {
+        let pattern = if options.regex { query.to_string() } else { regex::escape(query) };
+
+        let regex = RegexBuilder::new(pattern.as_str())
+            .case_insensitive(!options.case_sensitive)
+            .multi_line(true)
+            .build()
+            .map_err(|err| err.to_string())?;
+
+        Ok(Self {
+            query           : query.to_string(),
+            options,
+
+            regex,
+
+            text            : String::new(),
+            matches         : vec![],
+            byte_ranges     : vec![],
+            valid           : false,
+        })
+    }
+
+    /// The (start, end) char indices of all matches in the text, empty matches are skipped
+    pub fn find_all(&self, text: &str) -> Vec<(usize, usize)> {
+        self.find_ranges(text).into_iter().map(|(chars, _)| chars).collect()
+    }
+
+    /// The char range and the byte range of every match in the text
+    fn find_ranges(&self, text: &str) -> Vec<((usize, usize), (usize, usize))> {
+        if self.query.is_empty() {
+            return vec![];
+        }
+
+        let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+
+        let mut ranges = vec![];
+
+        // Byte offsets are converted to char indices while walking through the text once
+        let mut byte = 0;
+        let mut index = 0;
+        let mut char_index = |to: usize| {
+            index += text[byte..to].chars().count();
+            byte = to;
+            index
+        };
+
+        for m in self.regex.find_iter(text) {
+            if m.start() == m.end() {
+                continue;
+            }
+            if self.options.whole_word && (is_word(text[..m.start()].chars().next_back()) || is_word(text[m.end()..].chars().next())) {
+                continue;
+            }
+            let start = char_index(m.start());
+            let end = char_index(m.end());
+            ranges.push(((start, end), (m.start(), m.end())));
+        }
+        ranges
+    }
+
+    /// The text replacing the match at the given char range of the text, expands the captures of
+    /// a regular expression. The range has to be one of the matches.
+    pub fn replacement(&mut self, text: &TextBuffer, start: usize, end: usize, replace: &str) -> String {
+        self.matches(text);
+        match self.matches.binary_search(&(start, end)) {
+            Ok(index) => self.expand(self.byte_ranges[index], replace),
+            Err(_) => replace.to_string(),
+        }
+    }
+
+    /// The char ranges of all matches together with the texts replacing them
+    pub fn replacements(&mut self, text: &TextBuffer, replace: &str) -> Vec<(usize, usize, String)> {
+        self.matches(text);
+        self.matches.iter().zip(self.byte_ranges.iter()).map(|(m, bytes)| (m.0, m.1, self.expand(*bytes, replace))).collect()
+    }
+
+    /// Expands the captures of the match at the given byte range of the searched text
+    fn expand(&self, (start, end): (usize, usize), replace: &str) -> String {
+        if !self.options.regex {
+            return replace.to_string();
+        }
+
+        // Match again at the start of the range, the surrounding text matters for anchors
+        if let Some(captures) = self.regex.captures_at(&self.text, start) {
+            if let Some(m) = captures.get(0) {
+                if m.start() == start && m.end() == end {
+                    let mut expanded = String::new();
+                    captures.expand(replace, &mut expanded);
+                    return expanded;
+                }
+            }
+        }
+        replace.to_string()
+    }
+
+    /// The matches in the given text, these are cached until the text changes
+    pub fn matches(&mut self, text: &TextBuffer) -> &Vec<(usize, usize)> {
+        if !self.valid {
+            text.write_to(&mut self.text);
+            (self.matches, self.byte_ranges) = self.find_ranges(&self.text).into_iter().unzip();
+            self.valid = true;
+        }
+        &self.matches
+    }
+
+    /// Marks the matches as outdated after the text changed
+    pub fn invalidate(&mut self) {
+        self.valid = false;
+    }
+}
+
+/// The find bar, a mini-buffer drawn by the editor which searches as you type
+pub struct FindBar {
+    pub query               : String,
+    pub options             : SearchOptions,
+
+    /// The selections and the scroll offset when the bar was opened, restored by Escape
+    pub restore_selections  : SelectionSet,
+    pub restore_offset      : (isize, isize),
+}
@@ -0,0 +1,158 @@
+use serde::{ Deserialize, Serialize };
+
+/// A selection between the anchor, where the selection was started, and the head, where the
+/// cursor is. Both are char indices into the text, the selection is empty if they are equal.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Copy, Clone, Default)]
+pub struct Selection {
+    pub anchor              : usize,
+    pub head                : usize,
+}
+
+impl Selection {
+
+    pub fn new(anchor: usize, head: usize) -> Self {
+        Self {
+            anchor,
+            head,
+        }
+    }
+
+    /// An empty selection at the given char index
+    pub fn cursor(char_index: usize) -> Self {
+        Self::new(char_index, char_index)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    /// The first selected char index
+    pub fn start(&self) -> usize {
+        self.anchor.min(self.head)
+    }
+
+    /// The char index after the last selected char
+    pub fn end(&self) -> usize {
+        self.anchor.max(self.head)
+    }
+
+    /// True if the head is before the anchor, i.e. the selection was extended backwards
+    pub fn is_reversed(&self) -> bool {
+        self.head < self.anchor
+    }
+
+    /// Moves the head, the anchor stays if extend is true, otherwise the selection collapses
+    pub fn move_head(&mut self, head: usize, extend: bool) {
+        self.head = head;
+        if !extend {
+            self.anchor = head;
+        }
+    }
+}
+
+/// The selections of the editor, sorted by their start and without overlaps. One of them is the
+/// primary selection, its head is the main cursor.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct SelectionSet {
+    selections              : Vec<Selection>,
+    primary                 : usize,
+}
+
+impl Default for SelectionSet {
+    fn default() -> Self {
+        Self::new(Selection::default())
+    }
+}
+
+impl SelectionSet {
+
+    /// A set with a single selection
+    pub fn new(selection: Selection) -> Self {
+        Self {
+            selections      : vec![selection],
+            primary         : 0,
+        }
+    }
+
+    /// Creates a set from the given selections, overlapping selections are merged
+    pub fn from_vec(selections: Vec<Selection>, primary: usize) -> Self {
+        if selections.is_empty() {
+            return Self::default();
+        }
+
+        let mut set = Self {
+            primary         : primary.min(selections.len() - 1),
+            selections,
+        };
+        set.normalize();
+        set
+    }
+
+    pub fn primary(&self) -> Selection {
+        self.selections[self.primary]
+    }
+
+    pub fn primary_index(&self) -> usize {
+        self.primary
+    }
+
+    pub fn len(&self) -> usize {
+        self.selections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.selections.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Selection> {
+        self.selections.iter()
+    }
+
+    pub fn to_vec(&self) -> Vec<Selection> {
+        self.selections.clone()
+    }
+
+    /// Adds the selection and makes it the primary selection
+    pub fn add(&mut self, selection: Selection) {
+        self.selections.push(selection);
+        self.primary = self.selections.len() - 1;
+        self.normalize();
+    }
+
+    /// Sorts the selections and merges the overlapping ones, the primary selection survives a merge
+    fn normalize(&mut self) {
+        let primary = self.selections[self.primary];
+
+        let mut selections = self.selections.clone();
+        selections.sort_by_key(|s| (s.start(), s.end()));
+
+        let mut merged : Vec<Selection> = vec![];
+        let mut primary_index = 0;
+
+        for selection in selections {
+            let is_primary = selection == primary;
+
+            if let Some(last) = merged.last_mut() {
+                if selection.start() < last.end() || selection.start() == last.start() {
+                    let start = last.start();
+                    let end = last.end().max(selection.end());
+                    // Keep the direction of the primary selection
+                    let reversed = if is_primary { selection.is_reversed() } else { last.is_reversed() };
+                    *last = if reversed { Selection::new(end, start) } else { Selection::new(start, end) };
+                    if is_primary {
+                        primary_index = merged.len() - 1;
+                    }
+                    continue;
+                }
+            }
+
+            if is_primary {
+                primary_index = merged.len();
+            }
+            merged.push(selection);
+        }
+
+        self.selections = merged;
+        self.primary = primary_index;
+    }
+}
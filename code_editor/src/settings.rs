@@ -2,6 +2,32 @@ pub struct Settings {
 
     pub line_number_width           : usize,
 
+    /// Draw spaces and tabs in the whitespace color of the theme
+    pub show_whitespace             : bool,
+
+    /// The amount of (columns, lines) kept visible around the cursor when scrolling to it
+    pub scroll_margin               : (usize, usize),
+
+    /// The distance of the tab stops in columns, also the width of one level of indentation. Use
+    /// set_tab_width() of the editor to change it for a loaded text.
+    pub tab_width                   : usize,
+    /// Tab and auto-indent insert spaces instead of tabs
+    pub insert_spaces               : bool,
+    /// Take the tab width and insert_spaces from the indentation of the text in set_text()
+    pub detect_indentation          : bool,
+
+    /// Typing an opening bracket or quote of the language also inserts the closing one
+    pub auto_close                  : bool,
+
+    /// The maximum amount of undo steps and the maximum amount of bytes they keep in memory, the
+    /// oldest steps are removed first
+    pub undo_entries                : usize,
+    pub undo_memory                 : usize,
+    /// The maximum size of a saved undo history in bytes, the oldest steps are left out
+    pub undo_file_size              : usize,
+    /// Keep the undone steps as a branch of the undo tree when a new edit is made after an undo
+    pub undo_tree                   : bool,
+
 }
 
 impl Settings {
@@ -9,6 +35,21 @@ impl Settings {
     pub fn new() -> Self {
         Self {
             line_number_width       : 100,
+
+            show_whitespace         : false,
+
+            scroll_margin           : (4, 2),
+
+            tab_width               : 4,
+            insert_spaces           : true,
+            detect_indentation      : true,
+
+            auto_close              : true,
+
+            undo_entries            : 1000,
+            undo_memory             : 32 * 1024 * 1024,
+            undo_file_size          : 4 * 1024 * 1024,
+            undo_tree               : false,
         }
     }
 }
\ No newline at end of file
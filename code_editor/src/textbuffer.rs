@@ -0,0 +1,352 @@
+use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The indentation style of a text
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Indentation {
+    Tabs,
+    /// The given amount of spaces per level
+    Spaces(usize),
+}
+
+/// The text storage of the editor. A rope with a line index, inserts and deletes are O(log n).
+/// Positions are either char indices into the whole text or (column, line) pairs where the column
+/// counts grapheme clusters, i.e. what the user perceives as a single character.
+pub struct TextBuffer {
+    rope                    : Rope,
+}
+
+impl From<&str> for TextBuffer {
+    fn from(text: &str) -> Self {
+        Self {
+            rope            : Rope::from_str(text),
+        }
+    }
+}
+
+impl std::fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for chunk in self.rope.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for TextBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextBuffer {
+
+    pub fn new() -> Self {
+        Self {
+            rope            : Rope::new(),
+        }
+    }
+
+    /// The number of chars in the buffer
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    /// The number of lines, a trailing line feed starts a new (empty) line
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rope.len_chars() == 0
+    }
+
+    /// Inserts the text at the given char index
+    pub fn insert(&mut self, char_index: usize, text: &str) {
+        let char_index = char_index.min(self.rope.len_chars());
+        self.rope.insert(char_index, text);
+    }
+
+    /// Inserts a char at the given char index
+    pub fn insert_char(&mut self, char_index: usize, c: char) {
+        let char_index = char_index.min(self.rope.len_chars());
+        self.rope.insert_char(char_index, c);
+    }
+
+    /// Removes the chars in the given range
+    pub fn remove(&mut self, start: usize, end: usize) {
+        let end = end.min(self.rope.len_chars());
+        if start < end {
+            self.rope.remove(start..end);
+        }
+    }
+
+    /// Returns the char at the given index
+    pub fn char(&self, char_index: usize) -> Option<char> {
+        if char_index < self.rope.len_chars() {
+            Some(self.rope.char(char_index))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the text between the two char indices
+    pub fn slice(&self, start: usize, end: usize) -> String {
+        let end = end.min(self.rope.len_chars());
+        if start < end {
+            self.rope.slice(start..end).to_string()
+        } else {
+            "".to_string()
+        }
+    }
+
+    /// Returns the given line without its line ending
+    pub fn line(&self, line: usize) -> String {
+        if line < self.rope.len_lines() {
+            let start = self.rope.line_to_char(line);
+            self.slice(start, start + self.line_len_chars(line))
+        } else {
+            "".to_string()
+        }
+    }
+
+    /// The number of graphemes in the given line without its line ending
+    pub fn line_len(&self, line: usize) -> usize {
+        self.line(line).graphemes(true).count()
+    }
+
+    /// The number of chars in the given line without its line ending
+    fn line_len_chars(&self, line: usize) -> usize {
+        if line >= self.rope.len_lines() {
+            return 0;
+        }
+        let l = self.rope.line(line);
+        let mut len = l.len_chars();
+        if len > 0 && l.char(len - 1) == '\n' {
+            len -= 1;
+            if len > 0 && l.char(len - 1) == '\r' {
+                len -= 1;
+            }
+        }
+        len
+    }
+
+    /// The line ending of the text, "\r\n" if the first line ends with it, otherwise "\n"
+    pub fn line_ending(&self) -> &'static str {
+        if self.rope.len_lines() > 1 && self.line_len_chars(0) + 2 == self.rope.line(0).len_chars() {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+
+    /// The char index of the start of the given line
+    pub fn line_to_char(&self, line: usize) -> usize {
+        if line >= self.rope.len_lines() {
+            self.rope.len_chars()
+        } else {
+            self.rope.line_to_char(line)
+        }
+    }
+
+    /// The line of the given char index
+    pub fn char_to_line(&self, char_index: usize) -> usize {
+        self.rope.char_to_line(char_index.min(self.rope.len_chars()))
+    }
+
+    /// Converts a (column, line) position to a char index, the position is clamped to the text
+    pub fn pos_to_char(&self, pos: (usize, usize)) -> usize {
+        if pos.1 >= self.rope.len_lines() {
+            return self.rope.len_chars();
+        }
+        let chars : usize = self.line(pos.1).graphemes(true).take(pos.0).map(|g| g.chars().count()).sum();
+        self.rope.line_to_char(pos.1) + chars
+    }
+
+    /// Converts a char index to a (column, line) position. An index inside a grapheme cluster
+    /// snaps to the start of the cluster.
+    pub fn char_to_pos(&self, char_index: usize) -> (usize, usize) {
+        let char_index = char_index.min(self.rope.len_chars());
+        let line = self.rope.char_to_line(char_index);
+
+        let mut chars = char_index - self.rope.line_to_char(line);
+        let mut column = 0;
+
+        for g in self.line(line).graphemes(true) {
+            let count = g.chars().count();
+            if count > chars {
+                break;
+            }
+            chars -= count;
+            column += 1;
+        }
+        (column, line)
+    }
+
+    /// Replaces the contents of the string with the text, reusing its allocation
+    pub fn write_to(&self, string: &mut String) {
+        string.clear();
+        string.reserve(self.rope.len_bytes());
+        for chunk in self.rope.chunks() {
+            string.push_str(chunk);
+        }
+    }
+
+    /// The 64 bit FNV-1a hash of the text, identifies a text for example in a saved undo history
+    pub fn hash(&self) -> u64 {
+        let mut hash : u64 = 0xcbf29ce484222325;
+        for chunk in self.rope.chunks() {
+            for byte in chunk.bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+
+    /// The display column of every grapheme boundary of the given line, from the start to the end
+    /// of the line. A tab advances to the next multiple of the tab width, other graphemes by one.
+    pub fn display_columns(&self, line: usize, tab_width: usize) -> Vec<usize> {
+        let tab_width = tab_width.max(1);
+        let mut columns = vec![0];
+        let mut column = 0;
+
+        for g in self.line(line).graphemes(true) {
+            column = if g == "\t" { (column / tab_width + 1) * tab_width } else { column + 1 };
+            columns.push(column);
+        }
+        columns
+    }
+
+    /// The display column of the given (column, line) position, see display_columns()
+    pub fn display_column(&self, pos: (usize, usize), tab_width: usize) -> usize {
+        let columns = self.display_columns(pos.1, tab_width);
+        columns[pos.0.min(columns.len() - 1)]
+    }
+
+    /// The column of the last grapheme boundary of the line at or before the display column
+    pub fn column_at_display(&self, line: usize, display_column: usize, tab_width: usize) -> usize {
+        self.display_columns(line, tab_width).iter().rposition(|c| *c <= display_column).unwrap_or(0)
+    }
+
+    /// Guesses the indentation of the text from its indented lines. Tabs win if more lines start
+    /// with a tab than with spaces, otherwise the most common change of the indentation between
+    /// lines is the amount of spaces per level. Only the first 10000 lines are looked at. Returns
+    /// None if no line is indented.
+    pub fn detect_indentation(&self) -> Option<Indentation> {
+        let mut tabs = 0;
+        let mut spaces = 0;
+        // How often the indentation changed by 1 to 8 spaces
+        let mut changes = [0; 9];
+        let mut previous = 0;
+
+        for line in 0..self.len_lines().min(10000) {
+            let text = self.line(line);
+            if text.trim().is_empty() {
+                continue;
+            }
+            if text.starts_with('\t') {
+                tabs += 1;
+                continue;
+            }
+
+            let indent = text.chars().take_while(|c| *c == ' ').count();
+            if indent > 0 {
+                spaces += 1;
+            }
+            let change = indent.abs_diff(previous);
+            if change < changes.len() {
+                changes[change] += 1;
+            }
+            previous = indent;
+        }
+
+        if tabs == 0 && spaces == 0 {
+            None
+        } else if tabs > spaces {
+            Some(Indentation::Tabs)
+        } else {
+            // On a tie the smaller width wins
+            let mut width = 1;
+            for (change, count) in changes.iter().enumerate().skip(2) {
+                if *count > changes[width] {
+                    width = change;
+                }
+            }
+            if changes[width] > 0 { Some(Indentation::Spaces(width)) } else { None }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_remove() {
+        let mut buffer = TextBuffer::new();
+        assert!(buffer.is_empty());
+
+        buffer.insert(0, "hello world");
+        buffer.insert_char(5, ',');
+        buffer.insert(100, "!");
+        assert_eq!(buffer.to_string(), "hello, world!");
+        assert_eq!(buffer.len_chars(), 13);
+
+        buffer.remove(5, 6);
+        buffer.remove(11, 100);
+        buffer.remove(3, 3);
+        assert_eq!(buffer.to_string(), "hello world");
+        assert_eq!(buffer.char(4), Some('o'));
+        assert_eq!(buffer.char(11), None);
+        assert_eq!(buffer.slice(6, 100), "world");
+    }
+
+    #[test]
+    fn lines() {
+        let buffer = TextBuffer::from("one\ntwo\r\n\nlast");
+        assert_eq!(buffer.len_lines(), 4);
+        assert_eq!(buffer.line(1), "two");
+        assert_eq!(buffer.line(2), "");
+        assert_eq!(buffer.line(4), "");
+
+        assert_eq!(buffer.line_len_chars(0), 3);
+        assert_eq!(buffer.line_len_chars(1), 3);
+        assert_eq!(buffer.line_len_chars(2), 0);
+        assert_eq!(buffer.line_len_chars(9), 0);
+
+        // The last line has no line ending
+        assert_eq!(buffer.line(3), "last");
+        assert_eq!(buffer.line_len_chars(3), 4);
+
+        // A trailing line feed starts an empty last line
+        let buffer = TextBuffer::from("a\r\n");
+        assert_eq!(buffer.len_lines(), 2);
+        assert_eq!(buffer.line(0), "a");
+        assert_eq!(buffer.line(1), "");
+
+        // The first line decides the line ending
+        assert_eq!(buffer.line_ending(), "\r\n");
+        assert_eq!(TextBuffer::from("one\ntwo\r\n").line_ending(), "\n");
+        assert_eq!(TextBuffer::from("one").line_ending(), "\n");
+    }
+
+    #[test]
+    fn char_and_line() {
+        let buffer = TextBuffer::from("one\ntwo\r\nthree");
+        assert_eq!(buffer.line_to_char(0), 0);
+        assert_eq!(buffer.line_to_char(1), 4);
+        assert_eq!(buffer.line_to_char(2), 9);
+        assert_eq!(buffer.line_to_char(3), 14);
+
+        assert_eq!(buffer.char_to_line(3), 0);
+        assert_eq!(buffer.char_to_line(4), 1);
+        assert_eq!(buffer.char_to_line(8), 1);
+        assert_eq!(buffer.char_to_line(100), 2);
+
+        assert_eq!(buffer.pos_to_char((2, 1)), 6);
+        assert_eq!(buffer.pos_to_char((0, 5)), 14);
+        assert_eq!(buffer.char_to_pos(6), (2, 1));
+        assert_eq!(buffer.char_to_pos(14), (5, 2));
+    }
+}
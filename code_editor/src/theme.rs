@@ -1,33 +1,177 @@
+use serde::{ Deserialize, Serialize };
 
+/// The font style of a theme entry. Bold and italic text uses the bold and italic fonts of the
+/// font family set in the editor.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[serde(default)]
+pub struct FontStyle {
+    pub bold                : bool,
+    pub italic              : bool,
+    pub underline           : bool,
+    pub strikethrough       : bool,
+}
+
+impl FontStyle {
+
+    pub fn bold() -> Self {
+        Self { bold: true, ..Default::default() }
+    }
+
+    pub fn italic() -> Self {
+        Self { italic: true, ..Default::default() }
+    }
+
+    pub fn underline() -> Self {
+        Self { underline: true, ..Default::default() }
+    }
+
+    /// Parses a TextMate font style like "bold italic"
+    pub fn from_textmate(text: &str) -> Self {
+        let mut style = Self::default();
+        for word in text.split_whitespace() {
+            match word {
+                "bold" => style.bold = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "strikethrough" => style.strikethrough = true,
+                _ => {}
+            }
+        }
+        style
+    }
+}
+
+/// The font styles of the theme entries which color text, the names match the color fields.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(default)]
+pub struct Styles {
+    pub text                : FontStyle,
+    pub identifier          : FontStyle,
+    pub number              : FontStyle,
+    pub keywords            : FontStyle,
+    pub brackets            : FontStyle,
+    pub comments            : FontStyle,
+    pub string              : FontStyle,
+    pub attribute           : FontStyle,
+    pub key                 : FontStyle,
+    pub heading             : FontStyle,
+    pub emphasis            : FontStyle,
+    pub link                : FontStyle,
+    pub error               : FontStyle,
+}
+
+impl Default for Styles {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Styles {
+
+    /// Plain code, bold headings, italic emphasis and underlined links
+    pub fn new() -> Self {
+        Self {
+            text            : FontStyle::default(),
+            identifier      : FontStyle::default(),
+            number          : FontStyle::default(),
+            keywords        : FontStyle::default(),
+            brackets        : FontStyle::default(),
+            comments        : FontStyle::default(),
+            string          : FontStyle::default(),
+            attribute       : FontStyle::default(),
+            key             : FontStyle::default(),
+            heading         : FontStyle::bold(),
+            emphasis        : FontStyle::italic(),
+            link            : FontStyle::underline(),
+            error           : FontStyle::default(),
+        }
+    }
+}
+
+/// The colors of the editor. Themes can be saved and loaded as TOML or JSON where colors are
+/// written as "#RRGGBB" or "#RRGGBBAA", VS Code color themes can be imported.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(default)]
 pub struct Theme {
 
+    pub name                : String,
+
+    #[serde(with = "hex_color")]
     pub background          : [u8;4],
+    #[serde(with = "hex_color")]
     pub line_numbers        : [u8;4],
+    #[serde(with = "hex_color")]
     pub line_numbers_bg     : [u8;4],
+    #[serde(with = "hex_color")]
+    pub gutter_separator    : [u8;4],
 
+    #[serde(with = "hex_color")]
     pub text                : [u8;4],
+    #[serde(with = "hex_color")]
     pub cursor              : [u8;4],
+    #[serde(with = "hex_color")]
+    pub selection           : [u8;4],
+    #[serde(with = "hex_color")]
+    pub current_line        : [u8;4],
+    #[serde(with = "hex_color")]
+    pub search_match        : [u8;4],
+    #[serde(with = "hex_color")]
+    pub whitespace          : [u8;4],
 
+    #[serde(with = "hex_color")]
     pub identifier          : [u8;4],
+    #[serde(with = "hex_color")]
     pub number              : [u8;4],
+    #[serde(with = "hex_color")]
     pub keywords            : [u8;4],
+    #[serde(with = "hex_color")]
     pub brackets            : [u8;4],
+    #[serde(with = "hex_color")]
     pub comments            : [u8;4],
+    #[serde(with = "hex_color")]
     pub string              : [u8;4],
+    #[serde(with = "hex_color")]
+    pub attribute           : [u8;4],
+    #[serde(with = "hex_color")]
+    pub key                 : [u8;4],
+
+    #[serde(with = "hex_color")]
+    pub heading             : [u8;4],
+    #[serde(with = "hex_color")]
+    pub emphasis            : [u8;4],
+    #[serde(with = "hex_color")]
+    pub link                : [u8;4],
 
+    #[serde(with = "hex_color")]
     pub error               : [u8;4],
+
+    pub styles              : Styles,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Theme {
 
+    /// The default dark theme
     pub fn new() -> Self {
         Self {
+            name            : "Dark".to_string(),
+
             background      : [34, 34, 36, 255],
             line_numbers    : [160, 160, 160, 255],
             line_numbers_bg : [30, 30, 32, 255],
+            gutter_separator: [52, 52, 56, 255],
 
             text            : [255, 255, 255, 255],
             cursor          : [170, 170, 170, 255],
+            selection       : [45, 133, 200, 255],
+            current_line    : [40, 40, 43, 255],
+            search_match    : [150, 110, 30, 160],
+            whitespace      : [80, 80, 84, 255],
 
             identifier      : [120, 214, 255, 255],
             number          : [159, 197, 146, 255],
@@ -35,8 +179,350 @@ impl Theme {
             brackets        : [226, 73, 146, 212],
             comments        : [69, 128, 56, 212],
             string          : [197, 117, 92, 212],
+            attribute       : [197, 134, 192, 255],
+            key             : [156, 220, 254, 255],
+
+            heading         : [45, 133, 200, 255],
+            emphasis        : [220, 220, 170, 255],
+            link            : [78, 201, 176, 255],
 
             error           : [237, 55, 54, 255],
+
+            styles          : Styles::new(),
+        }
+    }
+
+    /// A light theme
+    pub fn light() -> Self {
+        Self {
+            name            : "Light".to_string(),
+
+            background      : [255, 255, 255, 255],
+            line_numbers    : [140, 140, 140, 255],
+            line_numbers_bg : [246, 246, 246, 255],
+            gutter_separator: [224, 224, 224, 255],
+
+            text            : [30, 30, 30, 255],
+            cursor          : [40, 40, 40, 255],
+            selection       : [173, 214, 255, 255],
+            current_line    : [244, 244, 250, 255],
+            search_match    : [250, 190, 70, 150],
+            whitespace      : [200, 200, 200, 255],
+
+            identifier      : [0, 16, 128, 255],
+            number          : [9, 134, 88, 255],
+            keywords        : [0, 0, 255, 255],
+            brackets        : [175, 0, 219, 255],
+            comments        : [0, 128, 0, 255],
+            string          : [163, 21, 21, 255],
+            attribute       : [128, 0, 128, 255],
+            key             : [4, 81, 165, 255],
+
+            heading         : [0, 0, 255, 255],
+            emphasis        : [128, 0, 0, 255],
+            link            : [0, 112, 193, 255],
+
+            error           : [205, 49, 49, 255],
+
+            styles          : Styles::new(),
+        }
+    }
+
+    /// A black theme with strong colors
+    pub fn high_contrast() -> Self {
+        Self {
+            name            : "High Contrast".to_string(),
+
+            background      : [0, 0, 0, 255],
+            line_numbers    : [255, 255, 255, 255],
+            line_numbers_bg : [0, 0, 0, 255],
+            gutter_separator: [111, 195, 223, 255],
+
+            text            : [255, 255, 255, 255],
+            cursor          : [255, 255, 255, 255],
+            selection       : [38, 79, 120, 255],
+            current_line    : [0, 0, 0, 255],
+            search_match    : [255, 160, 0, 170],
+            whitespace      : [124, 124, 124, 255],
+
+            identifier      : [156, 254, 255, 255],
+            number          : [181, 255, 128, 255],
+            keywords        : [86, 156, 255, 255],
+            brackets        : [255, 215, 0, 255],
+            comments        : [124, 166, 104, 255],
+            string          : [255, 160, 122, 255],
+            attribute       : [255, 128, 255, 255],
+            key             : [212, 212, 255, 255],
+
+            heading         : [86, 156, 255, 255],
+            emphasis        : [255, 255, 0, 255],
+            link            : [111, 195, 223, 255],
+
+            error           : [255, 60, 60, 255],
+
+            styles          : Styles::new(),
         }
     }
-}
\ No newline at end of file
+
+    /// The names of the built-in themes
+    pub fn names() -> Vec<&'static str> {
+        vec!["Dark", "Light", "High Contrast"]
+    }
+
+    /// Returns the built-in theme with the given name, the comparison ignores case
+    pub fn named(name: &str) -> Option<Self> {
+        [Self::new(), Self::light(), Self::high_contrast()].into_iter().find(|theme| theme.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Parses a theme in TOML format, missing colors are taken from the dark theme
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|err| err.to_string())
+    }
+
+    /// Parses a theme in JSON format, missing colors are taken from the dark theme
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        serde_json::from_str(text).map_err(|err| err.to_string())
+    }
+
+    pub fn to_toml(&self) -> Result<String, String> {
+        toml::to_string(self).map_err(|err| err.to_string())
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|err| err.to_string())
+    }
+
+    /// Loads a theme from a .toml or .json file. JSON files with a "tokenColors" or "colors"
+    /// entry are treated as VS Code color themes.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        if path.to_lowercase().ends_with(".json") {
+            if text.contains("\"tokenColors\"") || text.contains("\"colors\"") {
+                Self::from_vscode(text.as_str())
+            } else {
+                Self::from_json(text.as_str())
+            }
+        } else {
+            Self::from_toml(text.as_str())
+        }
+    }
+
+    /// Saves the theme as a .toml or .json file
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = if path.to_lowercase().ends_with(".json") { self.to_json()? } else { self.to_toml()? };
+        std::fs::write(path, text).map_err(|err| err.to_string())
+    }
+
+    /// Imports a VS Code / TextMate color theme. The workbench colors are mapped to the editor
+    /// colors, the token colors are matched against the scope of every token type.
+    pub fn from_vscode(text: &str) -> Result<Self, String> {
+        let json : serde_json::Value = serde_json::from_str(strip_json_comments(text).as_str()).map_err(|err| err.to_string())?;
+
+        let mut theme = match json.get("type").and_then(|t| t.as_str()) {
+            Some("light") => Self::light(),
+            Some("hc") | Some("hcDark") => Self::high_contrast(),
+            _ => Self::new(),
+        };
+
+        if let Some(name) = json.get("name").and_then(|n| n.as_str()) {
+            theme.name = name.to_string();
+        }
+
+        if let Some(colors) = json.get("colors") {
+            let color = |key: &str| colors.get(key).and_then(|c| c.as_str()).and_then(parse_hex_color);
+
+            if let Some(c) = color("editor.background") {
+                theme.background = c;
+                theme.line_numbers_bg = c;
+                theme.current_line = c;
+            }
+            let targets : [(&str, &mut [u8;4]); 10] = [
+                ("editor.foreground", &mut theme.text),
+                ("editorLineNumber.foreground", &mut theme.line_numbers),
+                ("editorGutter.background", &mut theme.line_numbers_bg),
+                ("editorRuler.foreground", &mut theme.gutter_separator),
+                ("editorCursor.foreground", &mut theme.cursor),
+                ("editor.selectionBackground", &mut theme.selection),
+                ("editor.lineHighlightBackground", &mut theme.current_line),
+                ("editor.findMatchHighlightBackground", &mut theme.search_match),
+                ("editorWhitespace.foreground", &mut theme.whitespace),
+                ("editorError.foreground", &mut theme.error),
+            ];
+            for (key, target) in targets {
+                if let Some(c) = color(key) {
+                    *target = c;
+                }
+            }
+        }
+
+        // (scope, color, font style) of all token color rules
+        let mut rules : Vec<(String, Option<[u8;4]>, Option<FontStyle>)> = vec![];
+        if let Some(token_colors) = json.get("tokenColors").and_then(|t| t.as_array()) {
+            for rule in token_colors {
+                let settings = rule.get("settings");
+                let color = settings.and_then(|s| s.get("foreground")).and_then(|f| f.as_str()).and_then(parse_hex_color);
+                let style = settings.and_then(|s| s.get("fontStyle")).and_then(|f| f.as_str()).map(FontStyle::from_textmate);
+
+                let scopes : Vec<String> = match rule.get("scope") {
+                    Some(serde_json::Value::String(scope)) => scope.split(',').map(|s| s.trim().to_string()).collect(),
+                    Some(serde_json::Value::Array(scopes)) => scopes.iter().filter_map(|s| s.as_str()).map(|s| s.trim().to_string()).collect(),
+                    _ => vec![],
+                };
+                for scope in scopes {
+                    // Descendant selectors are not supported
+                    if !scope.is_empty() && !scope.contains(' ') {
+                        rules.push((scope, color, style));
+                    }
+                }
+            }
+        }
+
+        let styles = &mut theme.styles;
+        let targets : [(&str, &mut [u8;4], &mut FontStyle); 12] = [
+            ("variable.other", &mut theme.identifier, &mut styles.identifier),
+            ("constant.numeric", &mut theme.number, &mut styles.number),
+            ("keyword.control", &mut theme.keywords, &mut styles.keywords),
+            ("punctuation.bracket", &mut theme.brackets, &mut styles.brackets),
+            ("comment.line", &mut theme.comments, &mut styles.comments),
+            ("string.quoted", &mut theme.string, &mut styles.string),
+            ("entity.other.attribute-name", &mut theme.attribute, &mut styles.attribute),
+            ("support.type.property-name", &mut theme.key, &mut styles.key),
+            ("markup.heading", &mut theme.heading, &mut styles.heading),
+            ("markup.bold", &mut theme.emphasis, &mut styles.emphasis),
+            ("markup.underline.link", &mut theme.link, &mut styles.link),
+            ("invalid.illegal", &mut theme.error, &mut styles.error),
+        ];
+        for (target, color, style) in targets {
+            // The most specific matching rule wins, later rules win ties
+            let mut best_color : Option<(usize, [u8;4])> = None;
+            let mut best_style : Option<(usize, FontStyle)> = None;
+
+            for (scope, c, s) in &rules {
+                let matches = target == scope || (target.starts_with(scope.as_str()) && target.as_bytes().get(scope.len()) == Some(&b'.'));
+                if !matches {
+                    continue;
+                }
+                if let Some(c) = c {
+                    if best_color.is_none_or(|(len, _)| scope.len() >= len) {
+                        best_color = Some((scope.len(), *c));
+                    }
+                }
+                if let Some(s) = s {
+                    if best_style.is_none_or(|(len, _)| scope.len() >= len) {
+                        best_style = Some((scope.len(), *s));
+                    }
+                }
+            }
+            if let Some((_, c)) = best_color {
+                *color = c;
+            }
+            if let Some((_, s)) = best_style {
+                *style = s;
+            }
+        }
+
+        Ok(theme)
+    }
+}
+
+/// Parses "#RGB", "#RRGGBB" or "#RRGGBBAA"
+pub fn parse_hex_color(text: &str) -> Option<[u8;4]> {
+    let hex = text.trim().strip_prefix('#')?;
+    let digit = |i: usize| u8::from_str_radix(hex.get(i..i + 1)?, 16).ok();
+    let byte = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+
+    match hex.len() {
+        3 => Some([digit(0)? * 17, digit(1)? * 17, digit(2)? * 17, 255]),
+        6 => Some([byte(0)?, byte(2)?, byte(4)?, 255]),
+        8 => Some([byte(0)?, byte(2)?, byte(4)?, byte(6)?]),
+        _ => None,
+    }
+}
+
+/// Formats the color as "#RRGGBB", or "#RRGGBBAA" if it is not opaque
+pub fn to_hex_color(color: &[u8;4]) -> String {
+    if color[3] == 255 {
+        format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+    } else {
+        format!("#{:02x}{:02x}{:02x}{:02x}", color[0], color[1], color[2], color[3])
+    }
+}
+
+/// Removes the comments and trailing commas VS Code allows in its JSON files
+fn strip_json_comments(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            if c == '\\' {
+                if let Some(escaped) = chars.next() {
+                    out.push(escaped);
+                }
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            },
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push(c);
+                        break;
+                    }
+                }
+            },
+            ('/', Some('*')) => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            },
+            ('}', _) | (']', _) => {
+                // Drop a comma before the closing bracket
+                let trimmed = out.trim_end().len();
+                if out[..trimmed].ends_with(',') {
+                    out.truncate(trimmed - 1);
+                }
+                out.push(c);
+            },
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Serializes colors as hex strings, RGBA arrays are accepted as well
+mod hex_color {
+    use serde::{ Deserialize, Deserializer, Serializer };
+
+    pub fn serialize<S: Serializer>(color: &[u8;4], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(super::to_hex_color(color).as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8;4], D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Color {
+            Hex(String),
+            Rgba([u8;4]),
+        }
+
+        match Color::deserialize(deserializer)? {
+            Color::Hex(text) => super::parse_hex_color(text.as_str()).ok_or_else(|| serde::de::Error::custom(format!("invalid color {}", text))),
+            Color::Rgba(color) => Ok(color),
+        }
+    }
+}
@@ -1,17 +1,206 @@
+use crate::prelude::*;
+
+use serde::{ Deserialize, Serialize };
+
+/// A change of the text, the removed text at the char index was replaced by the inserted text
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Edit {
+    pub index               : usize,
+    pub removed             : String,
+    pub inserted            : String,
+}
+
+impl Edit {
+
+    pub fn new(index: usize, removed: &str, inserted: &str) -> Self {
+        Self {
+            index,
+            removed         : removed.to_string(),
+            inserted        : inserted.to_string(),
+        }
+    }
+
+    /// Merges the edit which directly follows this one into it, like the next typed char or the
+    /// next deleted char. Returns false if the edits can not be merged.
+    pub fn merge(&mut self, next: &Edit) -> bool {
+        let end = self.index + self.inserted.chars().count();
+
+        if next.removed.is_empty() && next.index == end {
+            // Inserted behind the inserted text, or where the text was deleted
+            self.inserted += next.inserted.as_str();
+            true
+        } else if self.inserted.is_empty() && next.inserted.is_empty() && next.index + next.removed.chars().count() == self.index {
+            // Deleted in front of the deleted text, i.e. backspace
+            self.index = next.index;
+            self.removed = next.removed.clone() + self.removed.as_str();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The amount of bytes the edit keeps in memory
+    pub fn size(&self) -> usize {
+        self.removed.len() + self.inserted.len()
+    }
+}
+
+/// What caused the edits of an undo step, decides which steps are merged
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Copy, Clone)]
+pub enum EditKind {
+    /// Typing chars other than whitespace, one word is one step
+    Typing,
+    /// Typing whitespace, line breaks and tabs. It ends a word and merges with the next word.
+    Whitespace,
+    /// Deleting chars with backspace
+    Delete,
+    Paste,
+    Cut,
+    Replace,
+    /// Indenting or outdenting lines
+    Indent,
+    /// All other edits, which are never merged
+    Other,
+}
+
+impl EditKind {
+
+    /// True if a step of this kind merges with a directly following step of the next kind
+    pub fn merges_with(&self, next: EditKind) -> bool {
+        matches!((self, next),
+            (EditKind::Typing, EditKind::Typing) |
+            (EditKind::Whitespace, EditKind::Whitespace) |
+            (EditKind::Whitespace, EditKind::Typing) |
+            (EditKind::Delete, EditKind::Delete))
+    }
+
+    /// The name of the kind in the undo history
+    pub fn label(&self) -> &'static str {
+        match self {
+            EditKind::Typing | EditKind::Whitespace => "Typing",
+            EditKind::Delete => "Delete",
+            EditKind::Paste => "Paste",
+            EditKind::Cut => "Cut",
+            EditKind::Replace => "Replace",
+            EditKind::Indent => "Indent",
+            EditKind::Other => "Edit",
+        }
+    }
+}
+
+/// A step in the undo history
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct UndoEntry {
+    pub label               : String,
+    pub time_stamp          : u128,
+}
+
+/// A read only view of the undo history
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct UndoHistory {
+    pub entries             : Vec<UndoEntry>,
+    /// The amount of applied entries, the entries from this position on can be redone
+    pub position            : usize,
+}
+
+/// An abandoned branch of the undo tree
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct UndoBranch {
+    /// The steps of the branch, the first one replaces the step at the position of the branch
+    pub entries             : Vec<UndoEntry>,
+}
+
+/// The time in milliseconds used for the time stamps of the undo steps
+pub type Clock = Box<dyn Fn() -> u128 + Send + Sync>;
+
+/// The system time in milliseconds since the Unix epoch
+pub fn system_clock() -> u128 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis()
+}
+
+/// An undo step, the edits are undone in reverse order
 pub struct Undo {
-    pub undo_data           : String,
-    pub undo_pos            : (usize, usize),
+    /// The edits in the order they were applied
+    pub edits               : Vec<Edit>,
+    pub kind                : EditKind,
+    /// The name in the undo history, a group has the label of its first step
+    pub label               : String,
+    /// The number of the step in the order the steps were added, starting at 1
+    pub seq                 : usize,
+    /// In the undo tree mode the abandoned branches which replace this step and the steps after it
+    pub branches            : Vec<Vec<Undo>>,
 
-    pub redo_data           : String,
-    pub redo_pos            : (usize, usize),
+    /// The selections before and after the edits
+    pub undo_selections     : SelectionSet,
+    pub redo_selections     : SelectionSet,
 
     pub time_stamp          : u128,
 }
 
+impl Undo {
+
+    /// The amount of bytes the step keeps in memory, without its branches
+    pub fn size(&self) -> usize {
+        self.edits.iter().map(|edit| edit.size()).sum()
+    }
+
+    /// The amount of bytes the step and its branches keep in memory
+    fn tree_size(&self) -> usize {
+        self.size() + self.branches.iter().flatten().map(|undo| undo.tree_size()).sum::<usize>()
+    }
+
+    fn entry(&self) -> UndoEntry {
+        UndoEntry { label: self.label.clone(), time_stamp: self.time_stamp }
+    }
+}
+
+/// An undo step in a saved undo history
+#[derive(Serialize, Deserialize)]
+struct SavedUndo {
+    edits                   : Vec<Edit>,
+    kind                    : EditKind,
+    label                   : String,
+    undo_selections         : Vec<Selection>,
+    undo_primary            : usize,
+    redo_selections         : Vec<Selection>,
+    redo_primary            : usize,
+    time_stamp              : u64,
+}
+
+/// The format of a saved undo history
+#[derive(Serialize, Deserialize)]
+struct SavedHistory {
+    version                 : u32,
+    /// The hash of the text the history belongs to, as hex digits
+    hash                    : String,
+    /// The amount of applied steps
+    position                : usize,
+    steps                   : Vec<SavedUndo>,
+}
+
+impl SavedHistory {
+    const VERSION           : u32 = 1;
+}
+
 pub struct UndoStack {
     pub stack               : Vec<Undo>,
 
     pub index               : isize,
+
+    /// Keep the undone steps as a branch when a new step is added after an undo, instead of
+    /// removing them
+    pub tree                : bool,
+    /// The sequence number of the next step
+    next_seq                : usize,
+
+    /// The nesting depth of begin_group() calls and if the open group has a step already
+    group_depth             : usize,
+    group_started           : bool,
+
+    clock                   : Clock,
 }
 
 impl UndoStack {
@@ -19,9 +208,45 @@ impl UndoStack {
         Self {
             stack           : vec![],
             index           : -1,
+
+            tree            : false,
+            next_seq        : 1,
+
+            group_depth     : 0,
+            group_started   : false,
+
+            clock           : Box::new(system_clock),
         }
     }
 
+    /// Replaces the clock of the time stamps, e.g. with a fixed time for tests
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Removes all steps, the clock stays
+    pub fn clear(&mut self) {
+        self.stack.clear();
+        self.index = -1;
+        self.next_seq = 1;
+        self.group_depth = 0;
+        self.group_started = false;
+    }
+
+    /// Starts a group, all steps added until the matching end_group() become a single step.
+    /// Groups can be nested.
+    pub fn begin_group(&mut self) {
+        if self.group_depth == 0 {
+            self.group_started = false;
+        }
+        self.group_depth += 1;
+    }
+
+    /// Ends a group started by begin_group()
+    pub fn end_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
+    }
+
     pub fn has_undo(&self) -> bool {
         self.index >= 0
     }
@@ -33,55 +258,265 @@ impl UndoStack {
         false
     }
 
-    pub fn undo(&mut self) -> (String, (usize, usize)) {
-        let rc = (self.stack[self.index as usize].undo_data.clone(), self.stack[self.index as usize].undo_pos.clone());
+    /// Returns the step to undo, its edits have to be reverted
+    pub fn undo(&mut self) -> &Undo {
         self.index -= 1;
-        rc
+        &self.stack[(self.index + 1) as usize]
     }
 
-    pub fn redo(&mut self) -> (String, (usize, usize)) {
+    /// Returns the step to redo, its edits have to be applied again
+    pub fn redo(&mut self) -> &Undo {
         self.index += 1;
-        let rc = (self.stack[self.index as usize].redo_data.clone(), self.stack[self.index as usize].redo_pos.clone());
-        rc
+        &self.stack[self.index as usize]
     }
 
-    pub fn add(&mut self, undo: String, undo_pos: (usize, usize), redo: String, redo_pos: (usize, usize)) {
+    /// Adds the edits as a step. The step is merged into the last step if both are in the same
+    /// group, or if the kinds merge and the selections did not change between them.
+    pub fn add(&mut self, edits: Vec<Edit>, kind: EditKind, undo_selections: SelectionSet, redo_selections: SelectionSet) {
+
+        let edits : Vec<Edit> = edits.into_iter().filter(|edit| edit.size() > 0).collect();
+        if edits.is_empty() {
+            return;
+        }
+
+        let time = (self.clock)();
+        let grouped = self.group_depth > 0;
 
-        if self.index >= 0 {
+        if self.index >= 0 && self.index == self.stack.len() as isize - 1 {
 
-            let time = self.get_time();
+            let last = &mut self.stack[(self.index) as usize];
 
-            // If the last item is less than 2s old, replace it
-            if time < self.stack[(self.index) as usize].time_stamp + 2000 {
+            let merge = if grouped { self.group_started } else { last.kind.merges_with(kind) && last.redo_selections == undo_selections };
+            if merge {
 
-                self.stack[(self.index) as usize].redo_data = redo;
-                self.stack[(self.index) as usize].redo_pos = redo_pos;
-                self.stack[(self.index) as usize].time_stamp = time;
+                for edit in edits {
+                    Self::push_edit(&mut last.edits, edit);
+                }
+                // A group is never merged with the steps after it
+                last.kind = if grouped { EditKind::Other } else { kind };
+                last.redo_selections = redo_selections;
+                last.time_stamp = time;
 
                 return;
             }
         }
 
-        let to_remove = self.stack.len() as isize - self.index - 1;
-        for _i in 0..to_remove {
-            self.stack.pop();
+        // The undone steps are removed, or become a branch of the new step
+        let mut tail = self.stack.split_off(self.position());
+        let mut branches = vec![];
+        if self.tree && !tail.is_empty() {
+            branches = std::mem::take(&mut tail[0].branches);
+            branches.push(tail);
+        }
+
+        let mut merged = vec![];
+        for edit in edits {
+            Self::push_edit(&mut merged, edit);
         }
 
         self.stack.push(Undo {
-            undo_data   : undo,
-            undo_pos    : undo_pos,
-            redo_data   : redo,
-            redo_pos    : redo_pos,
-            time_stamp  : self.get_time(),
+            edits           : merged,
+            kind            : if grouped { EditKind::Other } else { kind },
+            label           : kind.label().to_string(),
+            seq             : self.next_seq,
+            branches,
+            undo_selections,
+            redo_selections,
+            time_stamp      : time,
         });
+        self.next_seq += 1;
 
         self.index += 1;
+        self.group_started = grouped;
+    }
+
+    /// The labels and time stamps of the steps and the amount of applied steps
+    pub fn history(&self) -> UndoHistory {
+        UndoHistory {
+            entries         : self.stack.iter().map(|undo| UndoEntry { label: undo.label.clone(), time_stamp: undo.time_stamp }).collect(),
+            position        : self.position(),
+        }
+    }
+
+    /// The amount of applied steps
+    pub fn position(&self) -> usize {
+        (self.index + 1) as usize
+    }
+
+    /// The branches which replace the steps from the given position on
+    pub fn branches(&self, position: usize) -> Vec<UndoBranch> {
+        match self.stack.get(position) {
+            Some(undo) => undo.branches.iter().map(|branch| UndoBranch { entries: branch.iter().map(|undo| undo.entry()).collect() }).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Replaces the steps from the position on with the given branch, they become a branch
+    /// themselves. The steps from the position on must not be applied. Returns false if there
+    /// is no such branch.
+    pub fn switch_branch(&mut self, position: usize, branch: usize) -> bool {
+        if position < self.position() || position >= self.stack.len() || branch >= self.stack[position].branches.len() {
+            return false;
+        }
+
+        let mut tail = self.stack.split_off(position);
+        let mut branches = std::mem::take(&mut tail[0].branches);
+        let mut next = branches.remove(branch);
+        branches.push(tail);
+        next[0].branches = branches;
+        self.stack.extend(next);
+        true
+    }
+
+    /// The sequence number of the current state of the text, 0 before the first step
+    pub fn seq(&self) -> usize {
+        if self.index >= 0 { self.stack[self.index as usize].seq } else { 0 }
+    }
+
+    /// The sequence numbers of all steps in the tree
+    pub fn seqs(&self) -> Vec<usize> {
+        fn collect(steps: &[Undo], seqs: &mut Vec<usize>) {
+            for undo in steps {
+                seqs.push(undo.seq);
+                for branch in &undo.branches {
+                    collect(branch, seqs);
+                }
+            }
+        }
+        let mut seqs = vec![];
+        collect(&self.stack, &mut seqs);
+        seqs
+    }
+
+    /// Finds the step with the given sequence number. Returns the (position, branch) switches
+    /// which make it part of the history, in order, and the position after the step.
+    pub fn find(&self, seq: usize) -> Option<(Vec<(usize, usize)>, usize)> {
+        fn search(steps: &[Undo], start: usize, seq: usize, path: &mut Vec<(usize, usize)>) -> Option<usize> {
+            for (offset, undo) in steps.iter().enumerate() {
+                if undo.seq == seq {
+                    return Some(start + offset + 1);
+                }
+                for (index, branch) in undo.branches.iter().enumerate() {
+                    path.push((start + offset, index));
+                    if let Some(position) = search(branch, start + offset, seq, path) {
+                        return Some(position);
+                    }
+                    path.pop();
+                }
+            }
+            None
+        }
+        let mut path = vec![];
+        search(&self.stack, 0, seq, &mut path).map(|position| (path, position))
     }
 
-    fn get_time(&self) -> u128 {
-        let stop = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .expect("Time went backwards");
-            stop.as_millis()
+    /// Saves the steps as JSON for the text with the given hash, see TextBuffer::hash(). The
+    /// oldest steps are left out until the JSON is at most max_size bytes long. Branches of the
+    /// undo tree are not saved.
+    pub fn save(&self, hash: u64, max_size: usize) -> Result<String, String> {
+        let mut history = SavedHistory {
+            version         : SavedHistory::VERSION,
+            hash            : format!("{:016x}", hash),
+            position        : self.position(),
+            steps           : self.stack.iter().map(|undo| SavedUndo {
+                edits           : undo.edits.clone(),
+                kind            : undo.kind,
+                label           : undo.label.clone(),
+                undo_selections : undo.undo_selections.to_vec(),
+                undo_primary    : undo.undo_selections.primary_index(),
+                redo_selections : undo.redo_selections.to_vec(),
+                redo_primary    : undo.redo_selections.primary_index(),
+                time_stamp      : undo.time_stamp as u64,
+            }).collect(),
+        };
+
+        // Measure every step once, a step in the list is followed by a comma except for the last
+        let sizes = history.steps.iter().map(|step| serde_json::to_string(step).map(|json| json.len() + 1)).collect::<Result<Vec<usize>, _>>().map_err(|err| err.to_string())?;
+        let mut total : usize = sizes.iter().sum();
+        let (mut first, mut last) = (0, sizes.len());
+
+        while first < last {
+            let steps = std::mem::take(&mut history.steps);
+            let header = serde_json::to_string(&history).map_err(|err| err.to_string())?.len();
+            history.steps = steps;
+            if header + total - 1 <= max_size {
+                break;
+            }
+            // Steps which can be redone are only left out if all undo steps are gone
+            if history.position > 0 {
+                total -= sizes[first];
+                first += 1;
+                history.position -= 1;
+            } else {
+                last -= 1;
+                total -= sizes[last];
+            }
+        }
+
+        history.steps.truncate(last);
+        history.steps.drain(..first);
+        serde_json::to_string(&history).map_err(|err| err.to_string())
     }
-}
\ No newline at end of file
+
+    /// Replaces the steps with a history saved by save(). Fails if the history was saved for
+    /// another text than the one with the given hash.
+    pub fn load(&mut self, json: &str, hash: u64) -> Result<(), String> {
+        let history : SavedHistory = serde_json::from_str(json).map_err(|err| err.to_string())?;
+
+        if history.version != SavedHistory::VERSION {
+            return Err(format!("Unsupported undo history version {}", history.version));
+        }
+        if history.hash != format!("{:016x}", hash) {
+            return Err("The undo history belongs to a different text".to_string());
+        }
+        if history.position > history.steps.len() {
+            return Err("Invalid undo history position".to_string());
+        }
+
+        self.clear();
+        self.stack = history.steps.into_iter().enumerate().map(|(index, step)| Undo {
+            edits           : step.edits,
+            kind            : step.kind,
+            label           : step.label,
+            seq             : index + 1,
+            branches        : vec![],
+            undo_selections : SelectionSet::from_vec(step.undo_selections, step.undo_primary),
+            redo_selections : SelectionSet::from_vec(step.redo_selections, step.redo_primary),
+            time_stamp      : step.time_stamp as u128,
+        }).collect();
+        self.index = history.position as isize - 1;
+        self.next_seq = self.stack.len() + 1;
+        Ok(())
+    }
+
+    /// Removes the oldest steps until there are at most max_entries steps which keep at most
+    /// max_memory bytes. Steps which can be redone are only removed if all undo steps are gone.
+    /// A removed step takes its branches with it.
+    pub fn limit(&mut self, max_entries: usize, max_memory: usize) {
+        let mut memory = self.memory();
+
+        while !self.stack.is_empty() && (self.stack.len() > max_entries || memory > max_memory) {
+            if self.index >= 0 {
+                memory -= self.stack.remove(0).tree_size();
+                self.index -= 1;
+            } else if let Some(undo) = self.stack.pop() {
+                memory -= undo.tree_size();
+            }
+        }
+    }
+
+    /// The amount of bytes all steps, including the branches, keep in memory
+    pub fn memory(&self) -> usize {
+        self.stack.iter().map(|undo| undo.tree_size()).sum()
+    }
+
+    /// Appends the edit, merging it into the last edit if it directly follows it
+    fn push_edit(edits: &mut Vec<Edit>, edit: Edit) {
+        if let Some(last) = edits.last_mut() {
+            if last.merge(&edit) {
+                return;
+            }
+        }
+        edits.push(edit);
+    }
+}
@@ -0,0 +1,78 @@
+use code_editor::prelude::*;
+
+mod common;
+use common::{ editor_with_mode, type_text };
+
+#[test]
+fn closing_pairs() {
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    type_text(&mut e, "f(");
+    assert_eq!(e.get_text(), "f()");
+    assert_eq!(e.cursor_pos, (2, 0));
+
+    // Typing the closing char steps over it
+    type_text(&mut e, "\"a\")");
+    assert_eq!(e.get_text(), "f(\"a\")");
+    assert_eq!(e.cursor_pos, (6, 0));
+
+    // Not in front of a word and no quote after a word
+    let mut e = editor_with_mode("x", CodeEditorMode::Rust);
+    e.set_cursor((0, 0));
+    type_text(&mut e, "[");
+    assert_eq!(e.get_text(), "[x");
+    e.set_cursor((2, 0));
+    type_text(&mut e, "\"");
+    assert_eq!(e.get_text(), "[x\"");
+
+    // Return between the pair splits it
+    let mut e = editor_with_mode("fn main() ", CodeEditorMode::Rust);
+    e.set_cursor((10, 0));
+    type_text(&mut e, "{");
+    e.key_down(None, Some(WidgetKey::Return));
+    type_text(&mut e, "x");
+    assert_eq!(e.get_text(), "fn main() {\n    x\n}");
+}
+
+#[test]
+fn wrap_selection() {
+    let mut e = editor_with_mode("let x = a + b;", CodeEditorMode::Rust);
+    e.set_selection((8, 0), (13, 0));
+    type_text(&mut e, "(");
+    assert_eq!(e.get_text(), "let x = (a + b);");
+    assert_eq!(e.get_selected_text(), "a + b");
+}
+
+#[test]
+fn delete_pair() {
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    type_text(&mut e, "[(");
+    e.key_down(None, Some(WidgetKey::Delete));
+    assert_eq!(e.get_text(), "[]");
+    e.key_down(None, Some(WidgetKey::Delete));
+    assert_eq!(e.get_text(), "");
+}
+
+#[test]
+fn configuration() {
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    e.settings.auto_close = false;
+    type_text(&mut e, "(\"");
+    assert_eq!(e.get_text(), "(\"");
+
+    // Plain text has no pairs
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    e.set_mode(CodeEditorMode::Text);
+    type_text(&mut e, "(");
+    assert_eq!(e.get_text(), "(");
+
+    let mut language = LanguageDefinition::rust();
+    language.auto_close = Some(vec![("<".to_string(), ">".to_string())]);
+    assert_eq!(language.auto_close_pairs(), vec![('<', '>')]);
+    assert_eq!(LanguageDefinition::rust().auto_close_pairs(), vec![('(', ')'), ('{', '}'), ('[', ']'), ('"', '"')]);
+
+    let mut e = CodeEditor::new();
+    let mode = e.register_language(language);
+    e.set_mode(mode);
+    type_text(&mut e, "(<");
+    assert_eq!(e.get_text(), "(<>");
+}
@@ -0,0 +1,109 @@
+use code_editor::prelude::*;
+
+mod common;
+use common::editor;
+
+const TABLE : &str = "name = 1\nwidth = 20\nx = 300";
+
+#[test]
+fn keyboard_block() {
+    let mut e = editor(TABLE);
+    e.set_cursor((1, 0));
+
+    e.modifier_changed(true, false, true, false);
+    for key in [WidgetKey::Down, WidgetKey::Down, WidgetKey::Right, WidgetKey::Right, WidgetKey::Right] {
+        e.key_down(None, Some(key));
+    }
+    assert_eq!(e.get_selections().len(), 3);
+    assert_eq!(e.get_selected_text(), "ame\nidt\n = ");
+    assert_eq!(e.cursor_pos, (4, 2));
+
+    // Typing replaces the block on every line
+    e.modifier_changed(false, false, false, false);
+    e.key_down(Some('_'), None);
+    assert_eq!(e.get_text(), "n_ = 1\nw_h = 20\nx_300");
+}
+
+#[test]
+fn short_lines() {
+    let mut e = editor("long line\nab\nanother");
+    e.set_block_selection((4, 0), (6, 2));
+    assert_eq!(e.get_selected_text(), " l\n\nhe");
+    assert_eq!(e.get_selections()[1], Selection::cursor(12));
+}
+
+#[test]
+fn copy_and_paste_block() {
+    let mut e = editor(TABLE);
+    e.set_block_selection((0, 0), (1, 2));
+    assert_eq!(e.copy(), "n\nw\nx");
+
+    // A block pastes below each other, padding short lines and adding missing ones
+    e.set_cursor((9, 1));
+    e.paste("n\nw\nx".to_string());
+    assert_eq!(e.get_text(), "name = 1\nwidth = 2n0\nx = 300  w\n         x");
+    assert_eq!(e.cursor_pos, (10, 3));
+
+    e.undo();
+    assert_eq!(e.get_text(), TABLE);
+
+    // Cutting leaves a cursor on every line
+    e.set_block_selection((0, 0), (2, 2));
+    assert_eq!(e.cut(), "na\nwi\nx ");
+    assert_eq!(e.get_text(), "me = 1\ndth = 20\n= 300");
+    assert_eq!(e.get_selections().len(), 3);
+}
+
+#[test]
+fn alt_drag() {
+    let mut e = editor(TABLE);
+    let left = e.settings.line_number_width;
+
+    e.modifier_changed(false, false, true, false);
+    e.mouse_down((left + 5, 5));
+    e.mouse_dragged((left + 35, 2 * 22 + 5));
+    e.mouse_up((0, 0));
+    assert_eq!(e.get_selected_text(), "ame\nidt\n = ");
+}
+
+#[test]
+fn tabs() {
+    // The tab spans the display columns 1 to 4, "b" is at the same display column as "d"
+    let mut e = editor("a\tb\nabcd");
+    e.set_tab_width(4);
+
+    e.set_block_selection((3, 0), (4, 1));
+    assert_eq!(e.get_selected_text(), "\t\nd");
+    e.set_block_selection((4, 0), (5, 1));
+    assert_eq!(e.get_selected_text(), "b\n");
+
+    // The keyboard moves the head over the whole tab on its line
+    e.set_cursor((0, 0));
+    e.modifier_changed(true, false, true, false);
+    for key in [WidgetKey::Right, WidgetKey::Right, WidgetKey::Down] {
+        e.key_down(None, Some(key));
+    }
+    assert_eq!(e.get_selected_text(), "a\t\nabcd");
+
+    // On the line of the head Left moves by one column, the tab is still partly inside
+    e.key_down(None, Some(WidgetKey::Left));
+    assert_eq!(e.get_selected_text(), "a\t\nabc");
+    e.modifier_changed(false, false, false, false);
+
+    // Dragging selects by the drawn columns
+    let left = e.settings.line_number_width;
+    e.modifier_changed(false, false, true, false);
+    e.mouse_down((left + 15, 5));
+    e.mouse_dragged((left + 45, 22 + 5));
+    e.mouse_up((0, 0));
+    assert_eq!(e.get_selected_text(), "\tb\nbcd");
+    e.modifier_changed(false, false, false, false);
+
+    // A block pastes at the display column of the cursor
+    e.set_block_selection((0, 0), (1, 1));
+    assert_eq!(e.copy(), "a\na");
+    e.set_cursor((2, 0));
+    e.paste("a\na".to_string());
+    assert_eq!(e.get_text(), "a\tab\nabcda");
+    assert_eq!(e.cursor_pos, (5, 1));
+}
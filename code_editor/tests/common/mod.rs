@@ -0,0 +1,32 @@
+//! Helpers shared by the integration tests, not every test file uses all of them.
+#![allow(dead_code)]
+
+use code_editor::prelude::*;
+
+/// An editor with the given text and the cursor at the start
+pub fn editor(text: &str) -> CodeEditor {
+    let mut editor = CodeEditor::new();
+    editor.set_text(text.to_string());
+    editor
+}
+
+/// Types the text char by char
+pub fn type_text(editor: &mut CodeEditor, text: &str) {
+    for c in text.chars() {
+        editor.key_down(Some(c), None);
+    }
+}
+
+/// Presses the key and returns the new cursor position
+pub fn press(editor: &mut CodeEditor, key: WidgetKey) -> (usize, usize) {
+    editor.key_down(None, Some(key));
+    editor.cursor_pos
+}
+
+/// An editor in the given mode with the given text
+pub fn editor_with_mode(text: &str, mode: CodeEditorMode) -> CodeEditor {
+    let mut editor = CodeEditor::new();
+    editor.set_mode(mode);
+    editor.set_text(text.to_string());
+    editor
+}
@@ -0,0 +1,82 @@
+use code_editor::prelude::*;
+
+/// Replaces the given line of the text and tells the highlighter about it
+fn replace_line(highlighter: &mut Highlighter, text: &mut TextBuffer, line: usize, code: &str) {
+    let start = text.line_to_char(line);
+    text.remove(start, start + text.line(line).chars().count());
+    text.insert(start, code);
+    highlighter.lines_changed(line, 1, 1);
+}
+
+fn kinds(highlighter: &Highlighter, line: usize) -> Vec<TokenType> {
+    highlighter.line_tokens(line).iter().map(|t| t.kind).filter(|kind| *kind != TokenType::Space).collect()
+}
+
+#[test]
+fn rescan_changed_lines() {
+    let language = Language::new(LanguageDefinition::rhai());
+    let mut text = TextBuffer::from("let a = 1;\nlet b = 2;\nlet c = 3;\nlet d = 4;\nlet e = 5;");
+    let mut highlighter = Highlighter::new();
+    highlighter.reset(text.len_lines());
+
+    assert_eq!(highlighter.update(&text, &language, 4), [0, 1, 2, 3, 4]);
+    assert!(highlighter.update(&text, &language, 4).is_empty());
+
+    // Only the edited line is scanned again, the state after it did not change
+    replace_line(&mut highlighter, &mut text, 2, "c = 30;");
+    assert_eq!(highlighter.update(&text, &language, 4), [2]);
+    assert_eq!(kinds(&highlighter, 2)[..3], [TokenType::Identifier, TokenType::Operator, TokenType::Number]);
+    assert_eq!(kinds(&highlighter, 3)[0], TokenType::Keyword);
+}
+
+#[test]
+fn inserted_lines() {
+    let language = Language::new(LanguageDefinition::rhai());
+    let mut text = TextBuffer::from("let a = 1;\nlet b = 2;\nlet c = 3;");
+    let mut highlighter = Highlighter::new();
+    highlighter.reset(text.len_lines());
+    highlighter.update(&text, &language, 2);
+
+    // Splitting line 1 in two keeps the tokens of the lines around it
+    let start = text.line_to_char(1) + 5;
+    text.insert(start, "\n");
+    highlighter.lines_changed(1, 1, 2);
+    assert_eq!(highlighter.update(&text, &language, 3), [1, 2]);
+    assert_eq!(kinds(&highlighter, 2)[..2], [TokenType::Operator, TokenType::Number]);
+    assert_eq!(kinds(&highlighter, 3)[0], TokenType::Keyword);
+
+    // Only the lines up to last_line are scanned, the rest follow later
+    replace_line(&mut highlighter, &mut text, 0, "let x = 1;");
+    replace_line(&mut highlighter, &mut text, 3, "let z = 3;");
+    assert_eq!(highlighter.update(&text, &language, 1), [0]);
+    assert_eq!(highlighter.update(&text, &language, 3), [3]);
+}
+
+#[test]
+fn block_comment_propagates() {
+    let language = Language::new(LanguageDefinition::rust());
+    let mut text = TextBuffer::from("let a = 1;\nlet b = 2;\nlet c = 3;\nlet d = 4;\nlet e = 5;");
+    let mut highlighter = Highlighter::new();
+    highlighter.reset(text.len_lines());
+    highlighter.update(&text, &language, 4);
+    assert_eq!(kinds(&highlighter, 2)[0], TokenType::Keyword);
+
+    // Opening a block comment changes the state at the start of all following lines
+    replace_line(&mut highlighter, &mut text, 1, "let b = /* 2;");
+    assert_eq!(highlighter.update(&text, &language, 4), [1, 2, 3, 4]);
+    assert_eq!(highlighter.line_state(2), LineState::BlockComment { index: 0, depth: 1, doc: false });
+    assert_eq!(kinds(&highlighter, 3), [TokenType::BlockComment]);
+
+    // Closing it on line 2 rescans the lines up to where the states match again
+    replace_line(&mut highlighter, &mut text, 2, "*/ let c = 3;");
+    assert_eq!(highlighter.update(&text, &language, 4), [2, 3, 4]);
+    assert_eq!(highlighter.line_state(3), LineState::Normal);
+    assert_eq!(kinds(&highlighter, 3)[0], TokenType::Keyword);
+
+    // Only the lines up to last_line are scanned, the rest follow later. Block comments nest in
+    // Rust, so the comment on line 1 is nested now and the lines after it stay a comment.
+    replace_line(&mut highlighter, &mut text, 0, "/* let a = 1;");
+    assert_eq!(highlighter.update(&text, &language, 1), [0, 1]);
+    assert_eq!(highlighter.update(&text, &language, 4), [2, 3, 4]);
+    assert_eq!(kinds(&highlighter, 4), [TokenType::BlockComment]);
+}
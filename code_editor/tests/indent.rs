@@ -0,0 +1,164 @@
+use code_editor::prelude::*;
+
+mod common;
+use common::editor_with_mode;
+
+#[test]
+fn auto_indent() {
+    let mut e = editor_with_mode("fn main() {\n    let x = 1;", CodeEditorMode::Rust);
+    e.set_cursor((14, 1));
+    e.key_down(None, Some(WidgetKey::Return));
+    assert_eq!(e.get_text(), "fn main() {\n    let x = 1;\n    ");
+    assert_eq!(e.cursor_pos, (4, 2));
+
+    // One more level after an opening bracket
+    e.set_cursor((11, 0));
+    e.key_down(None, Some(WidgetKey::Return));
+    assert_eq!(e.get_text(), "fn main() {\n    \n    let x = 1;\n    ");
+    assert_eq!(e.cursor_pos, (4, 1));
+
+    // Inside the indentation only the part before the cursor is kept
+    let mut e = editor_with_mode("    x", CodeEditorMode::Rust);
+    e.set_cursor((2, 0));
+    e.key_down(None, Some(WidgetKey::Return));
+    assert_eq!(e.get_text(), "  \n    x");
+    assert_eq!(e.cursor_pos, (2, 1));
+}
+
+#[test]
+fn split_brackets() {
+    let mut e = editor_with_mode("  f({})", CodeEditorMode::Rust);
+    e.set_cursor((5, 0));
+    e.key_down(None, Some(WidgetKey::Return));
+    assert_eq!(e.get_text(), "  f({\n    \n  })");
+    assert_eq!(e.cursor_pos, (4, 1));
+
+    // A single undo step
+    e.undo();
+    assert_eq!(e.get_text(), "  f({})");
+}
+
+#[test]
+fn dedent_closing_bracket() {
+    let mut e = editor_with_mode("fn main() {", CodeEditorMode::Rust);
+    e.set_cursor((11, 0));
+    e.key_down(None, Some(WidgetKey::Return));
+    e.key_down(Some('}'), None);
+    assert_eq!(e.get_text(), "fn main() {\n}");
+    assert_eq!(e.cursor_pos, (1, 1));
+
+    // Only on a line which is blank up to the cursor
+    let mut e = editor_with_mode("    x", CodeEditorMode::Rust);
+    e.set_cursor((5, 0));
+    e.key_down(Some(')'), None);
+    assert_eq!(e.get_text(), "    x)");
+
+    // Languages can configure the brackets which indent
+    let mut language = LanguageDefinition::rust();
+    language.indent_brackets = Some(vec![("{".to_string(), "}".to_string())]);
+    let mut e = CodeEditor::new();
+    let mode = e.register_language(language);
+    e.set_mode(mode);
+    e.set_text("f(\n    ".to_string());
+    e.set_cursor((4, 1));
+    e.key_down(Some(')'), None);
+    assert_eq!(e.get_text(), "f(\n    )");
+    e.set_cursor((2, 0));
+    e.key_down(None, Some(WidgetKey::Return));
+    assert_eq!(e.get_text(), "f(\n\n    )");
+}
+
+#[test]
+fn detect_indentation() {
+    assert_eq!(TextBuffer::from("a\n  b\n    c\n  d").detect_indentation(), Some(Indentation::Spaces(2)));
+    assert_eq!(TextBuffer::from("a {\n    b {\n        c\n    }\n}").detect_indentation(), Some(Indentation::Spaces(4)));
+    assert_eq!(TextBuffer::from("a\n\tb\n\t\tc\n  d").detect_indentation(), Some(Indentation::Tabs));
+    assert_eq!(TextBuffer::from("a\nb").detect_indentation(), None);
+
+    let e = editor_with_mode("a\n\tb", CodeEditorMode::Rust);
+    assert!(!e.settings.insert_spaces);
+    let e = editor_with_mode("a\n  b", CodeEditorMode::Rust);
+    assert!(e.settings.insert_spaces);
+    assert_eq!(e.settings.tab_width, 2);
+}
+
+#[test]
+fn tab_stops() {
+    let mut e = editor_with_mode("a\tb\n\tc", CodeEditorMode::Rust);
+    assert_eq!(TextBuffer::from("a\tb").display_columns(0, 4), vec![0, 1, 4, 5]);
+
+    // The caret is drawn at the tab stop, 10 pixels per column without a font
+    e.set_cursor((2, 0));
+    assert_eq!(e.cursor_rect.0, 4 * 10 - 2);
+
+    // Vertical movement keeps the display column
+    e.key_down(None, Some(WidgetKey::Down));
+    assert_eq!(e.cursor_pos, (1, 1));
+
+    e.set_tab_width(8);
+    e.set_cursor((2, 0));
+    assert_eq!(e.cursor_rect.0, 8 * 10 - 2);
+}
+
+#[test]
+fn tab_key() {
+    let mut e = editor_with_mode("ab", CodeEditorMode::Rust);
+    e.set_cursor((1, 0));
+    e.key_down(None, Some(WidgetKey::Tab));
+    assert_eq!(e.get_text(), "a   b");
+
+    // Backspace in the indentation deletes back to the previous tab stop
+    let mut e = editor_with_mode("      x", CodeEditorMode::Rust);
+    e.settings.tab_width = 4;
+    e.set_cursor((6, 0));
+    e.key_down(None, Some(WidgetKey::Delete));
+    assert_eq!(e.get_text(), "    x");
+    e.key_down(None, Some(WidgetKey::Delete));
+    assert_eq!(e.get_text(), "x");
+
+    let mut e = editor_with_mode("a", CodeEditorMode::Rust);
+    e.settings.insert_spaces = false;
+    e.set_cursor((0, 0));
+    e.key_down(None, Some(WidgetKey::Tab));
+    assert_eq!(e.get_text(), "\ta");
+}
+
+#[test]
+fn indent_lines() {
+    let mut e = editor_with_mode("a\n\nb\nc", CodeEditorMode::Rust);
+    e.settings.tab_width = 2;
+    e.set_selection((0, 0), (0, 3));
+    e.key_down(None, Some(WidgetKey::Tab));
+    // Blank lines and the line where the selection ends at the start are not indented
+    assert_eq!(e.get_text(), "  a\n\n  b\nc");
+    assert_eq!(e.get_selected_text(), "  a\n\n  b\n");
+
+    e.modifier_changed(true, false, false, false);
+    e.key_down(None, Some(WidgetKey::Tab));
+    e.key_down(None, Some(WidgetKey::Tab));
+    assert_eq!(e.get_text(), "a\n\nb\nc");
+
+    // Shift + Tab also outdents the line of a cursor
+    e.set_text("\t\tx".to_string());
+    e.set_cursor((3, 0));
+    e.key_down(None, Some(WidgetKey::Tab));
+    assert_eq!(e.get_text(), "\tx");
+    assert_eq!(e.cursor_pos, (2, 0));
+
+    e.undo();
+    assert_eq!(e.get_text(), "\t\tx");
+}
+
+#[test]
+fn crlf() {
+    let mut e = editor_with_mode("fn main() {}\r\n", CodeEditorMode::Rust);
+    e.set_cursor((11, 0));
+    e.key_down(None, Some(WidgetKey::Return));
+    assert_eq!(e.get_text(), "fn main() {\r\n    \r\n}\r\n");
+    assert_eq!(e.cursor_pos, (4, 1));
+
+    // Backspace at the start of a line removes the whole line ending
+    e.set_cursor((0, 2));
+    e.key_down(None, Some(WidgetKey::Delete));
+    assert_eq!(e.get_text(), "fn main() {\r\n    }\r\n");
+}
@@ -0,0 +1,183 @@
+use code_editor::prelude::*;
+
+/// The non whitespace tokens of the first line as (kind, text) pairs
+fn tokens(mode: CodeEditorMode, code: &str) -> Vec<(TokenType, String)> {
+    let mut editor = CodeEditor::new();
+    editor.set_mode(mode);
+    editor.set_text(code.to_string());
+
+    editor.get_line_tokens(0).iter()
+        .filter(|t| t.kind != TokenType::Space)
+        .map(|t| (t.kind, code[t.start..t.end].to_string()))
+        .collect()
+}
+
+/// The text of all tokens of the given kind
+fn texts(mode: CodeEditorMode, code: &str, kind: TokenType) -> Vec<String> {
+    tokens(mode, code).into_iter().filter(|(k, _)| *k == kind).map(|(_, text)| text).collect()
+}
+
+#[test]
+fn rhai() {
+    let code = "const s = `sum: ${a + `x${ {b} }`}` + 'c'; // comment";
+    assert_eq!(texts(CodeEditorMode::Rhai, code, TokenType::Interpolation), vec!["${", "${", "}", "}"]);
+    assert_eq!(texts(CodeEditorMode::Rhai, code, TokenType::String), vec!["`sum: ", "`x", "`", "`", "'c'"]);
+    assert_eq!(texts(CodeEditorMode::Rhai, code, TokenType::Keyword), vec!["const"]);
+    assert_eq!(texts(CodeEditorMode::Rhai, code, TokenType::Comment), vec!["// comment"]);
+
+    let code = "for i in 0..=0x_ff { print(type_of(i)) /* a /* b */ c */ } //! doc";
+    assert_eq!(texts(CodeEditorMode::Rhai, code, TokenType::Keyword), vec!["for", "in"]);
+    assert_eq!(texts(CodeEditorMode::Rhai, code, TokenType::Builtin), vec!["print", "type_of"]);
+    assert_eq!(texts(CodeEditorMode::Rhai, code, TokenType::Operator), vec!["..="]);
+    assert_eq!(texts(CodeEditorMode::Rhai, code, TokenType::Number), vec!["0", "0x_ff"]);
+    assert_eq!(texts(CodeEditorMode::Rhai, code, TokenType::BlockComment), vec!["/* a /* b */ c */"]);
+    assert_eq!(texts(CodeEditorMode::Rhai, code, TokenType::DocComment), vec!["//! doc"]);
+}
+
+#[test]
+fn rust() {
+    let code = r###"#[derive(Debug, Clone)] let s = r#"a "quoted" b"#; 'x' /* a /* b */ c */"###;
+    assert_eq!(texts(CodeEditorMode::Rust, code, TokenType::Attribute), vec!["#[derive(Debug, Clone)]"]);
+    assert_eq!(texts(CodeEditorMode::Rust, code, TokenType::String), vec![r##"r#"a "quoted" b"#"##, "'x'"]);
+    assert_eq!(texts(CodeEditorMode::Rust, code, TokenType::BlockComment), vec!["/* a /* b */ c */"]);
+
+    let code = "fn f<'a>(x: &'a str) -> Option<u8> { b\"bytes\" }";
+    assert_eq!(texts(CodeEditorMode::Rust, code, TokenType::Builtin), vec!["str", "Option", "u8"]);
+    assert_eq!(texts(CodeEditorMode::Rust, code, TokenType::String), vec!["b\"bytes\""]);
+}
+
+#[test]
+fn toml() {
+    assert_eq!(texts(CodeEditorMode::Toml, "[package.metadata]", TokenType::Heading), vec!["[package.metadata]"]);
+    assert_eq!(texts(CodeEditorMode::Toml, "[[bin]] # binaries", TokenType::Heading), vec!["[[bin]]"]);
+
+    let code = "default-features = false # no std";
+    assert_eq!(tokens(CodeEditorMode::Toml, code), vec![
+        (TokenType::Key, "default-features".to_string()),
+        (TokenType::Operator, "=".to_string()),
+        (TokenType::Keyword, "false".to_string()),
+        (TokenType::Comment, "# no std".to_string()),
+    ]);
+
+    let code = "\"quoted key\" = { path = 'C:\\dir' }";
+    assert_eq!(texts(CodeEditorMode::Toml, code, TokenType::Key), vec!["\"quoted key\"", "path"]);
+    assert_eq!(texts(CodeEditorMode::Toml, code, TokenType::String), vec!["'C:\\dir'"]);
+}
+
+#[test]
+fn json() {
+    let code = "{ \"name\" : \"value\", \"list\": [1.5e3, true, null] }";
+    assert_eq!(texts(CodeEditorMode::Json, code, TokenType::Key), vec!["\"name\"", "\"list\""]);
+    assert_eq!(texts(CodeEditorMode::Json, code, TokenType::String), vec!["\"value\""]);
+    assert_eq!(texts(CodeEditorMode::Json, code, TokenType::Number), vec!["1.5e3"]);
+    assert_eq!(texts(CodeEditorMode::Json, code, TokenType::Keyword), vec!["true", "null"]);
+}
+
+#[test]
+fn lua() {
+    let code = "local s = [==[raw ]] string]==] --[[ block ]] --[=[ long ]] ]=] print(s) -- line";
+    assert_eq!(texts(CodeEditorMode::Lua, code, TokenType::String), vec!["[==[raw ]] string]==]"]);
+    assert_eq!(texts(CodeEditorMode::Lua, code, TokenType::BlockComment), vec!["--[[ block ]]", "--[=[ long ]] ]=]"]);
+    assert_eq!(texts(CodeEditorMode::Lua, code, TokenType::Comment), vec!["-- line"]);
+    assert_eq!(texts(CodeEditorMode::Lua, code, TokenType::Builtin), vec!["print"]);
+    assert_eq!(texts(CodeEditorMode::Lua, "t[1] = a ~= b", TokenType::Bracket), vec!["[", "]"]);
+}
+
+#[test]
+fn python() {
+    let code = "@dataclass def f(self): return f\"{x}\" + r'\\d' + '''doc''' # done";
+    assert_eq!(texts(CodeEditorMode::Python, code, TokenType::Attribute), vec!["@dataclass"]);
+    assert_eq!(texts(CodeEditorMode::Python, code, TokenType::String), vec!["f\"{x}\"", "r'\\d'", "'''doc'''"]);
+    assert_eq!(texts(CodeEditorMode::Python, code, TokenType::Comment), vec!["# done"]);
+    assert_eq!(texts(CodeEditorMode::Python, "a @ b", TokenType::Attribute), Vec::<String>::new());
+}
+
+#[test]
+fn shaders() {
+    assert_eq!(texts(CodeEditorMode::Glsl, "#version 330 core", TokenType::Attribute), vec!["#version"]);
+    let code = "uniform vec4 color; // tint";
+    assert_eq!(texts(CodeEditorMode::Glsl, code, TokenType::Keyword), vec!["uniform"]);
+    assert_eq!(texts(CodeEditorMode::Glsl, code, TokenType::Builtin), vec!["vec4"]);
+
+    let code = "@vertex fn main(@location(0) p: vec3f) -> @builtin(position) vec4f { /* /* */ */ }";
+    assert_eq!(texts(CodeEditorMode::Wgsl, code, TokenType::Attribute), vec!["@vertex", "@location", "@builtin"]);
+    assert_eq!(texts(CodeEditorMode::Wgsl, code, TokenType::Builtin), vec!["vec3f", "vec4f"]);
+    assert_eq!(texts(CodeEditorMode::Wgsl, code, TokenType::BlockComment), vec!["/* /* */ */"]);
+}
+
+#[test]
+fn markdown() {
+    assert_eq!(tokens(CodeEditorMode::Markdown, "## A *heading*"), vec![(TokenType::Heading, "## A *heading*".to_string())]);
+
+    let code = "- a **bold** and `code` [link](https://x.org) 42";
+    assert_eq!(texts(CodeEditorMode::Markdown, code, TokenType::Operator), vec!["- "]);
+    assert_eq!(texts(CodeEditorMode::Markdown, code, TokenType::Emphasis), vec!["**bold**"]);
+    assert_eq!(texts(CodeEditorMode::Markdown, code, TokenType::String), vec!["`code`"]);
+    assert_eq!(texts(CodeEditorMode::Markdown, code, TokenType::Link), vec!["[link]"]);
+    assert_eq!(texts(CodeEditorMode::Markdown, "a - b", TokenType::Operator), Vec::<String>::new());
+}
+
+#[test]
+fn modes() {
+    let editor = CodeEditor::new();
+    assert_eq!(editor.get_mode_by_extension("rs"), Some(CodeEditorMode::Rust));
+    assert_eq!(editor.get_mode_by_extension("frag"), Some(CodeEditorMode::Glsl));
+    assert_eq!(editor.get_mode_by_extension(".md"), Some(CodeEditorMode::Markdown));
+    assert_eq!(editor.get_mode_by_name("wgsl"), Some(CodeEditorMode::Wgsl));
+
+    let definition = LanguageDefinition::rust();
+    let json = serde_json::to_string(&definition).unwrap();
+    assert_eq!(LanguageDefinition::from_json(json.as_str()).unwrap(), definition);
+}
+
+#[test]
+fn custom_language() {
+    let definition = LanguageDefinition::from_toml(r##"
+name = "MyDsl"
+extensions = ["dsl"]
+keywords = ["if", "else", "fn"]
+builtins = ["print"]
+line_comments = ["#"]
+block_comments = [["/*", "*/"]]
+strings = ["\""]
+operators = ["=="]
+brackets = [["(", ")"], ["{", "}"]]
+
+[numbers]
+hex = true
+"##).unwrap();
+    assert_eq!(definition.name, "MyDsl");
+    assert!(definition.numbers.hex);
+    assert!(!definition.numbers.binary);
+
+    let mut editor = CodeEditor::new();
+    let mode = editor.register_language(definition);
+    assert_eq!(editor.get_mode_by_extension("dsl"), Some(mode));
+    assert_eq!(editor.get_mode_by_name("MyDsl"), Some(mode));
+    assert_ne!(mode, CodeEditorMode::Rhai);
+
+    let code = "fn f() { if x == 0x1f { print(\"a\") } } # done";
+    editor.set_mode(mode);
+    editor.set_text(code.to_string());
+    let tokens = editor.get_line_tokens(0);
+    let texts = |kind: TokenType| -> Vec<&str> {
+        tokens.iter().filter(|t| t.kind == kind).map(|t| &code[t.start..t.end]).collect()
+    };
+    assert_eq!(texts(TokenType::Keyword), vec!["fn", "if"]);
+    assert_eq!(texts(TokenType::Builtin), vec!["print"]);
+    assert_eq!(texts(TokenType::Number), vec!["0x1f"]);
+    assert_eq!(texts(TokenType::String), vec!["\"a\""]);
+    assert_eq!(texts(TokenType::Comment), vec!["# done"]);
+    assert_eq!(texts(TokenType::Operator), vec!["=="]);
+}
+
+#[test]
+fn invalid_language() {
+    assert!(LanguageDefinition::from_toml("name = ").is_err());
+    assert!(LanguageDefinition::from_toml("keywords = \"if\"").is_err());
+    assert!(LanguageDefinition::from_json("{ \"name\": 1 }").is_err());
+    assert!(LanguageDefinition::from_file("languages/missing.toml").is_err());
+
+    let mut editor = CodeEditor::new();
+    assert!(editor.load_language("languages/missing.toml").is_err());
+}
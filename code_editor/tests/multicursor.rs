@@ -0,0 +1,130 @@
+use code_editor::prelude::*;
+
+mod common;
+use common::editor;
+
+fn heads(editor: &CodeEditor) -> Vec<usize> {
+    editor.get_selections().iter().map(|s| s.head).collect()
+}
+
+#[test]
+fn selection_set() {
+    let mut set = SelectionSet::new(Selection::new(10, 12));
+    set.add(Selection::cursor(2));
+    set.add(Selection::new(14, 11));
+    assert_eq!(set.to_vec(), vec![Selection::cursor(2), Selection::new(14, 10)]);
+    assert_eq!(set.primary(), Selection::new(14, 10));
+
+    let set = SelectionSet::from_vec(vec![Selection::cursor(5), Selection::cursor(1), Selection::cursor(5)], 2);
+    assert_eq!(set.to_vec(), vec![Selection::cursor(1), Selection::cursor(5)]);
+    assert_eq!(set.primary_index(), 1);
+}
+
+#[test]
+fn typing_at_all_cursors() {
+    let mut e = editor("one\ntwo\nthree");
+    e.set_cursor((0, 0));
+    e.add_selection((0, 1), (0, 1));
+    e.add_selection((0, 2), (0, 2));
+
+    e.key_down(Some('-'), None);
+    e.key_down(Some(' '), None);
+    assert_eq!(e.get_text(), "- one\n- two\n- three");
+
+    e.key_down(None, Some(WidgetKey::Delete));
+    assert_eq!(e.get_text(), "-one\n-two\n-three");
+
+    // One undo step reverts the edit at every cursor
+    e.undo();
+    assert_eq!(e.get_text(), "- one\n- two\n- three");
+}
+
+#[test]
+fn movement() {
+    let mut e = editor("abc\nd\nefgh");
+    e.set_cursor((3, 0));
+    e.add_selection((3, 2), (3, 2));
+
+    e.key_down(None, Some(WidgetKey::Left));
+    assert_eq!(heads(&e), vec![2, 8]);
+
+    // Cursors moving onto the same position are merged
+    e.modifier_changed(false, false, false, true);
+    e.key_down(None, Some(WidgetKey::Up));
+    assert_eq!(heads(&e), vec![0]);
+
+    e.modifier_changed(false, false, false, false);
+    e.set_cursor((3, 0));
+    e.modifier_changed(true, false, true, false);
+    e.key_down(None, Some(WidgetKey::Down));
+    e.key_down(None, Some(WidgetKey::Down));
+    assert_eq!(e.get_selections().len(), 3);
+    // The cursors are a block of zero width, which keeps the column past the short line
+    assert_eq!(e.cursor_pos, (3, 2));
+
+    e.modifier_changed(false, false, false, false);
+    e.key_down(None, Some(WidgetKey::Escape));
+    assert_eq!(heads(&e), vec![9]);
+}
+
+#[test]
+fn next_occurrence() {
+    let mut e = editor("let value = value + other_value;\nvalue");
+    e.set_cursor((5, 0));
+    e.modifier_changed(false, true, false, false);
+
+    // The first press selects the word at the cursor
+    e.key_down(Some('d'), None);
+    assert_eq!(e.get_selected_text(), "value");
+
+    e.key_down(Some('d'), None);
+    e.key_down(Some('d'), None);
+    e.key_down(Some('d'), None);
+    assert_eq!(e.get_selections().iter().map(|s| s.start()).collect::<Vec<usize>>(), vec![4, 12, 26, 33]);
+
+    // Wraps around without adding the first selection twice
+    e.key_down(Some('d'), None);
+    assert_eq!(e.get_selections().len(), 4);
+
+    e.modifier_changed(false, false, false, false);
+    e.key_down(Some('x'), None);
+    assert_eq!(e.get_text(), "let x = x + other_x;\nx");
+}
+
+#[test]
+fn paste() {
+    let mut e = editor("a\nb\nc");
+    e.set_cursor((1, 0));
+    e.add_selection((1, 1), (1, 1));
+    e.add_selection((1, 2), (1, 2));
+
+    // A line per cursor
+    e.paste("1\n2\n3".to_string());
+    assert_eq!(e.get_text(), "a1\nb2\nc3");
+
+    e.paste("!".to_string());
+    assert_eq!(e.get_text(), "a1!\nb2!\nc3!");
+
+    e.set_selection((0, 0), (1, 0));
+    e.add_selection((0, 2), (1, 2));
+    assert_eq!(e.copy(), "a\nc");
+}
+
+#[test]
+fn backspace_crlf() {
+    let mut e = editor("abx\r\ncd");
+    e.set_cursor((0, 1));
+    e.key_down(None, Some(WidgetKey::Delete));
+    assert_eq!(e.get_text(), "abxcd");
+    assert_eq!(heads(&e), [3]);
+
+    // Every cursor joins its line with the previous one
+    let mut e = editor("a\r\nb\nc\r\nd");
+    e.set_cursor((0, 1));
+    e.add_selection((0, 2), (0, 2));
+    e.add_selection((0, 3), (0, 3));
+    e.key_down(None, Some(WidgetKey::Delete));
+    assert_eq!(e.get_text(), "abcd");
+    e.undo();
+    assert_eq!(e.get_text(), "a\r\nb\nc\r\nd");
+}
@@ -0,0 +1,135 @@
+use code_editor::prelude::*;
+
+/// The non whitespace tokens of every line as (kind, text) pairs
+fn lines(editor: &mut CodeEditor) -> Vec<Vec<(TokenType, String)>> {
+    let text = editor.get_text();
+    let lines : Vec<&str> = text.split('\n').collect();
+
+    (0..lines.len()).map(|line| {
+        editor.get_line_tokens(line).iter()
+            .filter(|t| t.kind != TokenType::Space)
+            .map(|t| (t.kind, lines[line][t.start..t.end].to_string()))
+            .collect()
+    }).collect()
+}
+
+fn editor(mode: CodeEditorMode, text: &str) -> CodeEditor {
+    let mut editor = CodeEditor::new();
+    editor.set_mode(mode);
+    editor.set_text(text.to_string());
+    editor
+}
+
+fn token(kind: TokenType, text: &str) -> (TokenType, String) {
+    (kind, text.to_string())
+}
+
+#[test]
+fn block_comments() {
+    let mut e = editor(CodeEditorMode::Rust, "a /* one\n/* nested */ two\nthree */ b\n/** doc\n*/ c");
+    assert_eq!(lines(&mut e), vec![
+        vec![token(TokenType::Identifier, "a"), token(TokenType::BlockComment, "/* one")],
+        vec![token(TokenType::BlockComment, "/* nested */ two")],
+        vec![token(TokenType::BlockComment, "three */"), token(TokenType::Identifier, "b")],
+        vec![token(TokenType::DocComment, "/** doc")],
+        vec![token(TokenType::DocComment, "*/"), token(TokenType::Identifier, "c")],
+    ]);
+
+    let mut e = editor(CodeEditorMode::Rust, "/**/ a");
+    assert_eq!(lines(&mut e), vec![vec![token(TokenType::BlockComment, "/**/"), token(TokenType::Identifier, "a")]]);
+}
+
+#[test]
+fn strings() {
+    let mut e = editor(CodeEditorMode::Python, "s = \"\"\"first\n\nlast\"\"\" + x");
+    assert_eq!(lines(&mut e), vec![
+        vec![token(TokenType::Identifier, "s"), token(TokenType::Operator, "="), token(TokenType::MultilineString, "\"\"\"first")],
+        vec![],
+        vec![token(TokenType::MultilineString, "last\"\"\""), token(TokenType::Operator, "+"), token(TokenType::Identifier, "x")],
+    ]);
+
+    // Single line strings only continue after the escape char
+    let mut e = editor(CodeEditorMode::Rhai, "\"a\\\nb\" c\n\"d\ne");
+    assert_eq!(lines(&mut e), vec![
+        vec![token(TokenType::MultilineString, "\"a\\")],
+        vec![token(TokenType::MultilineString, "b\""), token(TokenType::Identifier, "c")],
+        vec![token(TokenType::Quotation, "\""), token(TokenType::Identifier, "d")],
+        vec![token(TokenType::Identifier, "e")],
+    ]);
+
+    let mut e = editor(CodeEditorMode::Rust, "r##\"a \"# \n\"## b");
+    assert_eq!(lines(&mut e), vec![
+        vec![token(TokenType::MultilineString, "r##\"a \"# ")],
+        vec![token(TokenType::MultilineString, "\"##"), token(TokenType::Identifier, "b")],
+    ]);
+}
+
+#[test]
+fn templates() {
+    let mut e = editor(CodeEditorMode::Rhai, "`a ${\nx + 1\n} b\nc` d");
+    assert_eq!(lines(&mut e), vec![
+        vec![token(TokenType::String, "`a "), token(TokenType::Interpolation, "${")],
+        vec![token(TokenType::Identifier, "x"), token(TokenType::Operator, "+"), token(TokenType::Number, "1")],
+        vec![token(TokenType::Interpolation, "}"), token(TokenType::String, " b")],
+        vec![token(TokenType::String, "c`"), token(TokenType::Identifier, "d")],
+    ]);
+}
+
+#[test]
+fn edits_rescan_following_lines() {
+    let mut e = editor(CodeEditorMode::Rust, "a\nb\nc");
+    assert_eq!(lines(&mut e)[2], vec![token(TokenType::Identifier, "c")]);
+
+    // Opening a comment turns the following lines into comment lines
+    e.set_cursor((0, 0));
+    e.key_down(Some('/'), None);
+    e.key_down(Some('*'), None);
+    assert_eq!(lines(&mut e), vec![
+        vec![token(TokenType::BlockComment, "/*a")],
+        vec![token(TokenType::BlockComment, "b")],
+        vec![token(TokenType::BlockComment, "c")],
+    ]);
+
+    // Closing it on the second line restores the last line
+    e.set_cursor((1, 1));
+    e.key_down(Some('*'), None);
+    e.key_down(Some('/'), None);
+    assert_eq!(lines(&mut e)[1..], vec![
+        vec![token(TokenType::BlockComment, "b*/")],
+        vec![token(TokenType::Identifier, "c")],
+    ]);
+}
+
+#[test]
+fn scanner_state() {
+    let language = Language::new(LanguageDefinition::lua());
+    let mut scanner = Scanner::new("x = [[a\nb]] --[[c\nd", &language);
+
+    let kinds : Vec<TokenType> = scanner.scan_spans().iter().map(|t| t.kind).filter(|k| *k != TokenType::Space).collect();
+    assert_eq!(kinds, vec![
+        TokenType::Identifier, TokenType::Operator, TokenType::MultilineString, TokenType::LineFeed,
+        TokenType::MultilineString, TokenType::BlockComment, TokenType::LineFeed, TokenType::BlockComment,
+    ]);
+    assert_eq!(scanner.state(), LineState::BlockComment { index: 0, depth: 1, doc: false });
+}
+
+#[test]
+fn long_brackets() {
+    let mut e = editor(CodeEditorMode::Lua, "a --[==[ one ]]\ntwo ]==] b = [=[\n]] ]=] c");
+    assert_eq!(lines(&mut e), vec![
+        vec![token(TokenType::Identifier, "a"), token(TokenType::BlockComment, "--[==[ one ]]")],
+        vec![token(TokenType::BlockComment, "two ]==]"), token(TokenType::Identifier, "b"), token(TokenType::Operator, "="), token(TokenType::MultilineString, "[=[")],
+        vec![token(TokenType::MultilineString, "]] ]=]"), token(TokenType::Identifier, "c")],
+    ]);
+}
+
+#[test]
+fn fenced_code() {
+    let mut e = editor(CodeEditorMode::Markdown, "```rust\nlet a = 1;\n```\ntext");
+    assert_eq!(lines(&mut e), vec![
+        vec![token(TokenType::String, "```rust")],
+        vec![token(TokenType::String, "let a = 1;")],
+        vec![token(TokenType::String, "```")],
+        vec![token(TokenType::Text, "text")],
+    ]);
+}
@@ -0,0 +1,76 @@
+use code_editor::prelude::*;
+
+mod common;
+use common::{ editor, press };
+
+#[test]
+fn word_movement() {
+    let mut e = editor("let value = a.b(1);\n  next");
+    e.modifier_changed(false, true, false, false);
+
+    let stops : Vec<(usize, usize)> = (0..9).map(|_| press(&mut e, WidgetKey::Right)).collect();
+    assert_eq!(stops, vec![(3, 0), (9, 0), (11, 0), (13, 0), (14, 0), (15, 0), (16, 0), (17, 0), (19, 0)]);
+    assert_eq!(press(&mut e, WidgetKey::Right), (0, 1));
+    assert_eq!(press(&mut e, WidgetKey::Right), (6, 1));
+    assert_eq!(press(&mut e, WidgetKey::Left), (2, 1));
+    assert_eq!(press(&mut e, WidgetKey::Left), (0, 1));
+    assert_eq!(press(&mut e, WidgetKey::Left), (19, 0));
+    assert_eq!(press(&mut e, WidgetKey::Left), (17, 0));
+
+    // Alt moves by words too and shift extends the selection
+    e.modifier_changed(true, false, true, false);
+    press(&mut e, WidgetKey::Left);
+    press(&mut e, WidgetKey::Left);
+    assert_eq!(e.get_selected_text(), "(1");
+}
+
+#[test]
+fn smart_home() {
+    let mut e = editor("a\n    indented");
+    e.set_cursor((8, 1));
+    assert_eq!(press(&mut e, WidgetKey::Home), (4, 1));
+    assert_eq!(press(&mut e, WidgetKey::Home), (0, 1));
+    assert_eq!(press(&mut e, WidgetKey::Home), (4, 1));
+    assert_eq!(press(&mut e, WidgetKey::End), (12, 1));
+}
+
+#[test]
+fn document_movement() {
+    let mut e = editor("first\nsecond\nthird line");
+    e.set_cursor((2, 1));
+    e.modifier_changed(false, true, false, false);
+    assert_eq!(press(&mut e, WidgetKey::End), (10, 2));
+    assert_eq!(press(&mut e, WidgetKey::Home), (0, 0));
+
+    e.modifier_changed(true, false, false, true);
+    assert_eq!(press(&mut e, WidgetKey::Down), (10, 2));
+    assert_eq!(e.get_selected_text(), e.get_text());
+}
+
+#[test]
+fn desired_column() {
+    let mut e = editor("a long line\nab\n\nanother line");
+    e.set_cursor((8, 0));
+    assert_eq!(press(&mut e, WidgetKey::Down), (2, 1));
+    assert_eq!(press(&mut e, WidgetKey::Down), (0, 2));
+    assert_eq!(press(&mut e, WidgetKey::Down), (8, 3));
+    assert_eq!(press(&mut e, WidgetKey::Up), (0, 2));
+
+    // Horizontal movement picks a new column
+    assert_eq!(press(&mut e, WidgetKey::Up), (2, 1));
+    assert_eq!(press(&mut e, WidgetKey::Left), (1, 1));
+    assert_eq!(press(&mut e, WidgetKey::Up), (1, 0));
+    assert_eq!(press(&mut e, WidgetKey::Up), (0, 0));
+}
+
+#[test]
+fn pages() {
+    let text : Vec<String> = (0..100).map(|i| format!("line {}", i)).collect();
+    let mut e = editor(text.join("\n").as_str());
+    e.set_cursor((3, 0));
+
+    // Without a drawn rect a page is a single line
+    assert_eq!(press(&mut e, WidgetKey::PageDown), (3, 1));
+    assert_eq!(press(&mut e, WidgetKey::PageUp), (3, 0));
+    assert_eq!(press(&mut e, WidgetKey::PageUp), (0, 0));
+}
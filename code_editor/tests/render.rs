@@ -0,0 +1,104 @@
+use code_editor::prelude::*;
+
+/// The rect of a viewport of 10 lines
+fn rect(editor: &CodeEditor) -> (usize, usize, usize, usize) {
+    (0, 0, editor.settings.line_number_width + 200, 10 * 22)
+}
+
+fn draw(editor: &mut CodeEditor) {
+    let rect = rect(editor);
+    let mut frame = vec![0; rect.2 * rect.3 * 4];
+    editor.draw(&mut frame[..], rect, rect.2);
+}
+
+fn editor(lines: usize) -> CodeEditor {
+    let mut editor = CodeEditor::new();
+    editor.set_mode(CodeEditorMode::Text);
+    let text : Vec<String> = (0..lines).map(|i| format!("line {}", i)).collect();
+    editor.set_text(text.join("\n"));
+    editor
+}
+
+#[test]
+fn visible_lines() {
+    let mut e = editor(10_000);
+    assert!(e.get_cached_lines().is_empty());
+
+    // Only the 11 lines which touch the viewport are rendered
+    draw(&mut e);
+    assert_eq!(e.get_cached_lines(), (0..11).collect::<Vec<usize>>());
+}
+
+#[test]
+fn edit_invalidates_lines() {
+    let mut e = editor(100);
+    draw(&mut e);
+
+    e.set_cursor((6, 3));
+    e.key_down(Some('x'), None);
+    let cached = e.get_cached_lines();
+    assert_eq!(cached, (0..11).filter(|line| *line != 3).collect::<Vec<usize>>());
+
+    // A new line moves the cached lines below it
+    e.key_down(None, Some(WidgetKey::Return));
+    let cached = e.get_cached_lines();
+    assert_eq!(cached, [0, 1, 2, 5, 6, 7, 8, 9, 10, 11]);
+
+    draw(&mut e);
+    assert_eq!(e.get_cached_lines(), (0..12).collect::<Vec<usize>>());
+}
+
+#[test]
+fn evict_lines() {
+    let mut e = editor(10_000);
+    draw(&mut e);
+
+    // Scrolling drops the bitmaps of the lines far outside the viewport
+    for _ in 0..104 {
+        e.mouse_wheel((0, -97 * 22));
+        draw(&mut e);
+        assert!(e.get_cached_lines().len() <= 33);
+    }
+    let cached = e.get_cached_lines();
+    assert!(cached.iter().all(|line| *line >= 9_970));
+}
+
+#[test]
+fn tokens_before_draw() {
+    let mut e = CodeEditor::new();
+    e.set_mode(CodeEditorMode::Rust);
+    let text : Vec<String> = (0..100).map(|i| format!("let a{} = 1;", i)).collect();
+    e.set_text(text.join("\n"));
+    draw(&mut e);
+
+    // Opening a block comment changes the tokens of all following lines, scanning them for
+    // get_line_tokens() has to drop their bitmaps as well
+    e.set_cursor((0, 3));
+    e.key_down(Some('/'), None);
+    e.key_down(Some('*'), None);
+    assert_eq!(e.get_line_tokens(5)[0].kind, TokenType::BlockComment);
+    assert_eq!(e.get_cached_lines(), [0, 1, 2, 6, 7, 8, 9, 10]);
+
+    draw(&mut e);
+    assert_eq!(e.get_cached_lines(), (0..11).collect::<Vec<usize>>());
+}
+
+#[test]
+fn scrolled_past_end() {
+    let mut e = editor(100);
+    draw(&mut e);
+    e.set_cursor((7, 99));
+    e.paste("\nmore".repeat(10_000));
+    draw(&mut e);
+    e.mouse_wheel((0, -10_000 * 22));
+    draw(&mut e);
+    assert!(e.get_scroll_offset().1 > 9_000);
+
+    // Closing the find bar restores the offset from before the undo, past the end of the text
+    e.open_find_bar();
+    e.undo();
+    e.close_find_bar(true);
+    draw(&mut e);
+    assert!(e.get_scroll_offset().1 < 100);
+    assert!(e.get_cached_lines().iter().all(|line| *line < 100));
+}
@@ -0,0 +1,79 @@
+use code_editor::prelude::*;
+
+/// An editor with 100 lines of 30 chars, drawn into a viewport of 10 lines and 20 columns
+fn editor() -> CodeEditor {
+    let mut editor = CodeEditor::new();
+    let text : Vec<String> = (0..100).map(|i| format!("{:<30}", i)).collect();
+    editor.set_text(text.join("\n"));
+
+    let settings = &editor.settings;
+    let rect = (0, 0, settings.line_number_width + 20 * 10, 10 * 22);
+    let mut frame = vec![0; rect.2 * rect.3 * 4];
+    editor.draw(&mut frame[..], rect, rect.2);
+    editor
+}
+
+fn first_line(editor: &CodeEditor) -> usize {
+    editor.get_scroll_offset().1
+}
+
+#[test]
+fn keys_scroll() {
+    let mut e = editor();
+    assert_eq!(first_line(&e), 0);
+
+    for _ in 0..7 {
+        e.key_down(None, Some(WidgetKey::Down));
+    }
+    assert_eq!(first_line(&e), 0);
+
+    // The cursor keeps a margin of 2 lines to the bottom
+    e.key_down(None, Some(WidgetKey::Down));
+    assert_eq!(first_line(&e), 1);
+
+    e.modifier_changed(false, true, false, false);
+    e.key_down(None, Some(WidgetKey::End));
+    assert_eq!(first_line(&e), 92);
+
+    e.key_down(None, Some(WidgetKey::Home));
+    assert_eq!(first_line(&e), 0);
+
+    // Columns scroll with a margin of 4
+    e.modifier_changed(false, false, false, false);
+    e.key_down(None, Some(WidgetKey::End));
+    assert_eq!(e.get_scroll_offset(), (15, 0));
+    e.key_down(None, Some(WidgetKey::Home));
+    assert_eq!(e.get_scroll_offset(), (0, 0));
+}
+
+#[test]
+fn set_cursor_and_undo_scroll() {
+    let mut e = editor();
+    e.set_cursor((0, 50));
+    assert_eq!(first_line(&e), 43);
+
+    e.key_down(Some('x'), None);
+    e.set_cursor((0, 0));
+    assert_eq!(first_line(&e), 0);
+
+    e.undo();
+    assert_eq!(e.cursor_pos, (0, 50));
+    assert_eq!(first_line(&e), 43);
+}
+
+#[test]
+fn scroll_to_line() {
+    let mut e = editor();
+    e.scroll_to_line(50, ScrollAlign::Top);
+    assert_eq!(first_line(&e), 50);
+    e.scroll_to_line(50, ScrollAlign::Center);
+    assert_eq!(first_line(&e), 45);
+    e.scroll_to_line(50, ScrollAlign::Bottom);
+    assert_eq!(first_line(&e), 41);
+
+    // Visible lines do not scroll
+    e.scroll_to_line(45, ScrollAlign::Nearest);
+    assert_eq!(first_line(&e), 41);
+    e.scroll_to_line(30, ScrollAlign::Nearest);
+    assert_eq!(first_line(&e), 28);
+}
@@ -0,0 +1,156 @@
+use code_editor::prelude::*;
+
+mod common;
+use common::editor;
+
+fn options(case_sensitive: bool, whole_word: bool, regex: bool) -> SearchOptions {
+    SearchOptions { case_sensitive, whole_word, regex, ..Default::default() }
+}
+
+#[test]
+fn find_all() {
+    let mut e = editor("Foo foo food\nfoo_bar (foo)");
+
+    assert_eq!(e.set_search("foo", SearchOptions::default()), Ok(5));
+    assert_eq!(e.find_all(), vec![(0, 3), (4, 7), (8, 11), (13, 16), (22, 25)]);
+
+    assert_eq!(e.set_search("foo", options(true, false, false)), Ok(4));
+    assert_eq!(e.set_search("foo", options(false, true, false)), Ok(3));
+    assert_eq!(e.set_search("(foo)", options(false, false, false)), Ok(1));
+    assert_eq!(e.set_search("^fo+", options(false, false, true)), Ok(2));
+    assert!(e.set_search("fo(o", options(false, false, true)).is_err());
+
+    // Matches follow edits
+    e.set_search("foo", options(false, true, false)).unwrap();
+    e.set_cursor((0, 0));
+    e.key_down(Some('x'), None);
+    assert_eq!(e.find_all(), vec![(5, 8), (23, 26)]);
+
+    e.clear_search();
+    assert!(e.find_all().is_empty());
+}
+
+#[test]
+fn find_next_and_previous() {
+    let mut e = editor("a1 a2\na3");
+    e.set_search("a", SearchOptions::default()).unwrap();
+
+    assert!(e.find_next());
+    assert_eq!(e.get_selected_text(), "a");
+    assert_eq!(e.get_selection(), Selection::new(0, 1));
+    assert!(e.find_next());
+    assert!(e.find_next());
+    assert_eq!(e.cursor_pos, (1, 1));
+
+    // Wraps around in both directions
+    assert!(e.find_next());
+    assert_eq!(e.get_selection(), Selection::new(0, 1));
+    assert!(e.find_previous());
+    assert_eq!(e.get_selection(), Selection::new(6, 7));
+
+    e.set_search("a", SearchOptions { wrap_around: false, ..Default::default() }).unwrap();
+    assert!(!e.find_next());
+    assert!(e.find_previous());
+    assert_eq!(e.get_selection(), Selection::new(3, 4));
+}
+
+#[test]
+fn replace() {
+    let mut e = editor("let a = 1;\nlet b = 2;");
+    e.set_search("let", options(true, true, false)).unwrap();
+
+    // The first call selects the match, the second replaces it
+    assert!(!e.replace("var"));
+    assert!(e.replace("var"));
+    assert_eq!(e.get_text(), "var a = 1;\nlet b = 2;");
+    assert_eq!(e.get_selection(), Selection::new(11, 14));
+
+    let mut e = editor("let a = 1;\nlet b = 2;");
+    e.set_search(r"let (\w) = (\d)", options(true, false, true)).unwrap();
+    assert_eq!(e.replace_all("const ${1}: i32 = $2"), 2);
+    assert_eq!(e.get_text(), "const a: i32 = 1;\nconst b: i32 = 2;");
+
+    // A single undo step restores the text
+    e.undo();
+    assert_eq!(e.get_text(), "let a = 1;\nlet b = 2;");
+}
+
+#[test]
+fn find_bar() {
+    let mut e = editor("alpha beta\nalphabet\nalp");
+    e.set_cursor((2, 0));
+
+    e.modifier_changed(false, true, false, false);
+    e.key_down(Some('f'), None);
+    e.modifier_changed(false, false, false, false);
+    assert!(e.is_find_bar_open());
+
+    // Typing goes to the query and jumps to the first match after the cursor
+    for c in "alp".chars() {
+        e.key_down(Some(c), None);
+    }
+    assert_eq!(e.get_text(), "alpha beta\nalphabet\nalp");
+    assert_eq!(e.get_selection(), Selection::new(11, 14));
+
+    e.key_down(Some('h'), None);
+    assert_eq!(e.get_selection(), Selection::new(11, 15));
+    e.key_down(None, Some(WidgetKey::Delete));
+    assert_eq!(e.get_selection(), Selection::new(11, 14));
+
+    e.key_down(None, Some(WidgetKey::Return));
+    assert_eq!(e.get_selection(), Selection::new(20, 23));
+    e.modifier_changed(true, false, false, false);
+    e.key_down(None, Some(WidgetKey::Return));
+    e.key_down(None, Some(WidgetKey::Return));
+    assert_eq!(e.get_selection(), Selection::new(0, 3));
+    e.modifier_changed(false, false, false, false);
+
+    // The bar draws without a font
+    let mut frame = vec![0; 600 * 200 * 4];
+    e.draw(&mut frame[..], (0, 0, 600, 200), 600);
+
+    // Escape restores the cursor
+    e.key_down(None, Some(WidgetKey::Escape));
+    assert!(!e.is_find_bar_open());
+    assert_eq!(e.get_selection(), Selection::cursor(2));
+    assert!(e.find_all().is_empty());
+
+    // Moving the cursor keeps the match, the selection is the new query
+    e.set_selection((0, 0), (5, 0));
+    e.open_find_bar();
+    e.key_down(None, Some(WidgetKey::Return));
+    assert_eq!(e.get_selection(), Selection::new(11, 16));
+    e.key_down(None, Some(WidgetKey::Right));
+    assert!(!e.is_find_bar_open());
+    assert_eq!(e.get_selection(), Selection::cursor(16));
+}
+
+#[test]
+fn find_bar_narrow() {
+    let mut e = editor(&"a\n".repeat(10_001));
+    e.set_font(concat!(env!("CARGO_MANIFEST_DIR"), "/../fonts/Source_Code_Pro/static/SourceCodePro-Regular.ttf"));
+    e.open_find_bar();
+    e.key_down(Some('a'), None);
+    assert_eq!(e.find_all().len(), 10_001);
+
+    // A status which does not fit into a narrow bar is left out
+    e.set_cursor((0, 3));
+    for font_size in 10..20 {
+        e.set_font_size(font_size as f32);
+        for width in (200..400).step_by(10) {
+            let mut frame = vec![0; width * 100 * 4];
+            e.draw(&mut frame[..], (0, 0, width, 100), width);
+        }
+    }
+}
+
+#[test]
+fn replace_all_long_line() {
+    let mut e = editor(&"ab1 ".repeat(30_000));
+    e.set_search("b(\\d)", options(true, false, true)).unwrap();
+    assert_eq!(e.replace_all("${1}c"), 30_000);
+    assert_eq!(e.get_text(), "a1c ".repeat(30_000));
+
+    e.undo();
+    assert_eq!(e.get_text(), "ab1 ".repeat(30_000));
+}
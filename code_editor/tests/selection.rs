@@ -0,0 +1,73 @@
+use code_editor::prelude::*;
+
+mod common;
+use common::editor;
+
+#[test]
+fn shift_movement() {
+    let mut e = editor("hello\nworld");
+    e.set_cursor((2, 0));
+
+    e.modifier_changed(true, false, false, false);
+    e.key_down(None, Some(WidgetKey::Right));
+    e.key_down(None, Some(WidgetKey::Down));
+    assert_eq!(e.get_selection(), Selection::new(2, 9));
+    assert_eq!(e.get_selected_text(), "llo\nwor");
+
+    // The anchor stays when the selection is extended backwards past it
+    e.key_down(None, Some(WidgetKey::Home));
+    e.key_down(None, Some(WidgetKey::Up));
+    assert_eq!(e.get_selection(), Selection::new(2, 0));
+    assert!(e.get_selection().is_reversed());
+    assert_eq!(e.get_selected_text(), "he");
+
+    e.key_down(None, Some(WidgetKey::End));
+    assert_eq!(e.get_selected_text(), "llo");
+}
+
+#[test]
+fn collapse() {
+    let mut e = editor("hello world");
+    e.set_selection((2, 0), (7, 0));
+
+    e.key_down(None, Some(WidgetKey::Left));
+    assert_eq!(e.get_selection(), Selection::cursor(2));
+
+    e.set_selection((7, 0), (2, 0));
+    e.key_down(None, Some(WidgetKey::Right));
+    assert_eq!(e.get_selection(), Selection::cursor(7));
+}
+
+#[test]
+fn select_all() {
+    let mut e = editor("a\nbc\n");
+    e.modifier_changed(false, true, false, false);
+    e.key_down(Some('a'), None);
+    assert_eq!(e.get_selected_text(), "a\nbc\n");
+    assert_eq!(e.get_selection(), Selection::new(0, 5));
+}
+
+#[test]
+fn edit_selection() {
+    let mut e = editor("one two three");
+    e.set_selection((4, 0), (7, 0));
+
+    e.key_down(Some('X'), None);
+    assert_eq!(e.get_text(), "one X three");
+    assert!(e.get_selection().is_empty());
+
+    e.set_selection((0, 0), (4, 0));
+    e.key_down(None, Some(WidgetKey::Delete));
+    assert_eq!(e.get_text(), "X three");
+
+    e.undo();
+    assert_eq!(e.get_text(), "one X three");
+    e.undo();
+    assert_eq!(e.get_text(), "one two three");
+
+    e.set_selection((8, 0), (13, 0));
+    assert_eq!(e.cut(), "three");
+    e.set_cursor((0, 0));
+    e.paste("three ".to_string());
+    assert_eq!(e.get_text(), "three one two ");
+}
@@ -0,0 +1,86 @@
+use code_editor::prelude::*;
+
+#[test]
+fn named_themes() {
+    for name in Theme::names() {
+        assert_eq!(Theme::named(name).unwrap().name, name);
+    }
+    assert_eq!(Theme::named("light"), Some(Theme::light()));
+    assert_eq!(Theme::named("Solarized"), None);
+}
+
+#[test]
+fn round_trip() {
+    let theme = Theme::high_contrast();
+    assert_eq!(Theme::from_toml(theme.to_toml().unwrap().as_str()).unwrap(), theme);
+    assert_eq!(Theme::from_json(theme.to_json().unwrap().as_str()).unwrap(), theme);
+
+    let mut theme = Theme::light();
+    theme.selection = [10, 20, 30, 128];
+    let toml = theme.to_toml().unwrap();
+    assert!(toml.contains("selection = \"#0a141e80\""));
+    assert_eq!(Theme::from_toml(toml.as_str()).unwrap(), theme);
+}
+
+#[test]
+fn partial_theme() {
+    let theme = Theme::from_toml("name = \"Mine\"\nbackground = \"#102030\"\ntext = [1, 2, 3, 4]\nkeywords = \"#fff\"").unwrap();
+    assert_eq!(theme.name, "Mine");
+    assert_eq!(theme.background, [16, 32, 48, 255]);
+    assert_eq!(theme.text, [1, 2, 3, 4]);
+    assert_eq!(theme.keywords, [255, 255, 255, 255]);
+    assert_eq!(theme.string, Theme::new().string);
+
+    assert!(Theme::from_toml("background = \"#12\"").is_err());
+}
+
+#[test]
+fn font_styles() {
+    let theme = Theme::from_toml("[styles]\nkeywords = { bold = true }\ncomments = { italic = true, strikethrough = true }").unwrap();
+    assert_eq!(theme.styles.keywords, FontStyle::bold());
+    assert_eq!(theme.styles.comments, FontStyle { italic: true, strikethrough: true, ..Default::default() });
+    assert_eq!(theme.styles.string, FontStyle::default());
+    assert_eq!(theme.styles.link, FontStyle::underline());
+
+    assert_eq!(FontStyle::from_textmate("bold underline"), FontStyle { bold: true, underline: true, ..Default::default() });
+    assert_eq!(FontStyle::from_textmate(""), FontStyle::default());
+}
+
+#[test]
+fn vscode_theme() {
+    let json = r##"{
+        // A comment
+        "name": "Test Theme",
+        "type": "light",
+        "colors": {
+            "editor.background": "#fafafa",
+            "editor.foreground": "#383a42",
+            "editor.selectionBackground": "#e5e5e6cc", /* block */
+        },
+        "tokenColors": [
+            { "scope": "comment", "settings": { "foreground": "#a0a1a7", "fontStyle": "italic" } },
+            { "scope": ["keyword", "storage.type"], "settings": { "foreground": "#a626a4" } },
+            { "scope": "keyword.control", "settings": { "foreground": "#0000ff" } },
+            { "scope": "keyword.control.flow", "settings": { "foreground": "#ff0000" } },
+            { "scope": "string, constant.numeric", "settings": { "foreground": "#50a14f" } },
+            { "scope": "meta.tag string", "settings": { "foreground": "#123456" } },
+            { "scope": "support.type.property-name.json", "settings": { "foreground": "#e45649" } },
+        ]
+    }"##;
+
+    let theme = Theme::from_vscode(json).unwrap();
+    let light = Theme::light();
+
+    assert_eq!(theme.name, "Test Theme");
+    assert_eq!(theme.background, [250, 250, 250, 255]);
+    assert_eq!(theme.text, [56, 58, 66, 255]);
+    assert_eq!(theme.selection, [229, 229, 230, 204]);
+    assert_eq!(theme.comments, [160, 161, 167, 255]);
+    assert_eq!(theme.styles.comments, FontStyle::italic());
+    assert_eq!(theme.keywords, [0, 0, 255, 255]);
+    assert_eq!(theme.string, [80, 161, 79, 255]);
+    assert_eq!(theme.number, [80, 161, 79, 255]);
+    // A more specific scope does not match the generic token scope
+    assert_eq!(theme.key, light.key);
+    assert_eq!(theme.attribute, light.attribute);
+}
@@ -0,0 +1,401 @@
+use code_editor::prelude::*;
+
+mod common;
+use common::{ editor_with_mode, type_text };
+
+/// Undoes all steps, checks the original text and redoes them again
+fn round_trip(editor: &mut CodeEditor, original: &str) {
+    let edited = editor.get_text();
+
+    while editor.has_undo() {
+        editor.undo();
+    }
+    assert_eq!(editor.get_text(), original);
+
+    while editor.has_redo() {
+        editor.redo();
+    }
+    assert_eq!(editor.get_text(), edited);
+}
+
+#[test]
+fn edit_round_trip() {
+    let original = "fn main() {\n    let s = \"äöü 👍🏽\";\n}\n";
+    let mut e = editor_with_mode(original, CodeEditorMode::Rust);
+
+    e.set_cursor((14, 1));
+    type_text(&mut e, "x, (y");
+    e.key_down(None, Some(WidgetKey::Return));
+    e.key_down(None, Some(WidgetKey::Delete));
+    e.key_down(None, Some(WidgetKey::Delete));
+    e.set_selection((0, 0), (2, 0));
+    e.paste("pub fn\nfoo".to_string());
+    e.add_selection((0, 3), (0, 3));
+    e.key_down(None, Some(WidgetKey::Tab));
+    e.set_selection((5, 1), (10, 2));
+    e.cut();
+    e.set_search("s", SearchOptions::default()).unwrap();
+    e.replace_all("ß");
+    assert_ne!(e.get_text(), original);
+
+    round_trip(&mut e, original);
+}
+
+#[test]
+fn undo_steps() {
+    let mut stack = UndoStack::new();
+    let selections = SelectionSet::default();
+
+    stack.add(vec![Edit::new(0, "", "a"), Edit::new(1, "", "b")], EditKind::Typing, selections.clone(), selections.clone());
+    // Typed chars are merged into one edit
+    assert_eq!(stack.stack[0].edits, vec![Edit::new(0, "", "ab")]);
+
+    // Backspace extends the deleted text
+    stack.add(vec![Edit::new(1, "b", "")], EditKind::Delete, selections.clone(), selections.clone());
+    stack.add(vec![Edit::new(0, "a", "")], EditKind::Delete, selections.clone(), selections.clone());
+    assert_eq!(stack.stack.len(), 2);
+    assert_eq!(stack.stack[1].edits, vec![Edit::new(0, "ab", "")]);
+
+    // Steps without any change are ignored
+    stack.add(vec![Edit::new(3, "", "")], EditKind::Other, selections.clone(), selections.clone());
+    assert_eq!(stack.stack.len(), 2);
+}
+
+#[test]
+fn limits() {
+    let mut stack = UndoStack::new();
+    let selections = SelectionSet::default();
+
+    for index in 0..5 {
+        stack.add(vec![Edit::new(index, "", "abcd")], EditKind::Other, selections.clone(), selections.clone());
+    }
+    assert_eq!(stack.memory(), 20);
+
+    stack.limit(4, 1000);
+    assert_eq!(stack.stack.len(), 4);
+    assert_eq!(stack.stack[0].edits[0].index, 1);
+    assert_eq!(stack.index, 3);
+
+    // Undo steps are removed before redo steps
+    stack.undo();
+    stack.limit(10, 12);
+    assert_eq!(stack.stack.len(), 3);
+    assert_eq!(stack.index, 1);
+    assert!(stack.has_redo());
+
+    // The steps of an editor keep the changed text only, not copies of the document
+    let text = "let x = 1;\n".repeat(10_000);
+    let mut e = editor_with_mode(&text, CodeEditorMode::Rust);
+    e.settings.undo_memory = 16;
+    type_text(&mut e, "abc");
+    e.key_down(None, Some(WidgetKey::Delete));
+    e.undo();
+    e.undo();
+    assert_eq!(e.get_text(), text);
+}
+
+#[test]
+fn grouping() {
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    type_text(&mut e, "let x");
+    e.key_down(None, Some(WidgetKey::Return));
+    type_text(&mut e, "  y");
+    e.key_down(None, Some(WidgetKey::Delete));
+    e.key_down(None, Some(WidgetKey::Delete));
+
+    // Every word with the whitespace in front of it is one step, deleting is another
+    let mut texts = vec![e.get_text()];
+    while e.has_undo() {
+        e.undo();
+        texts.push(e.get_text());
+    }
+    assert_eq!(texts, vec!["let x\n", "let x\n  y", "let x", "let", ""]);
+
+    // Moving the cursor ends the word
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    type_text(&mut e, "ab");
+    e.set_cursor((1, 0));
+    type_text(&mut e, "c");
+    e.undo();
+    assert_eq!(e.get_text(), "ab");
+
+    // Pastes and cuts are steps of their own
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    e.paste("a".to_string());
+    e.paste("b".to_string());
+    e.select_all();
+    e.cut();
+    e.undo();
+    assert_eq!(e.get_text(), "ab");
+    e.undo();
+    assert_eq!(e.get_text(), "a");
+}
+
+#[test]
+fn undo_groups() {
+    let mut e = editor_with_mode("a b c", CodeEditorMode::Rust);
+    e.begin_undo_group();
+    e.set_search("b", SearchOptions::default()).unwrap();
+    e.replace_all("x");
+    e.begin_undo_group();
+    e.set_cursor((0, 0));
+    type_text(&mut e, "// ");
+    e.end_undo_group();
+    e.paste("!".to_string());
+    e.end_undo_group();
+    assert_eq!(e.get_text(), "// !a x c");
+
+    // Typing after the group is a new step
+    type_text(&mut e, "y");
+    e.undo();
+    assert_eq!(e.get_text(), "// !a x c");
+    e.undo();
+    assert_eq!(e.get_text(), "a b c");
+    assert!(!e.has_undo());
+
+    e.redo();
+    assert_eq!(e.get_text(), "// !a x c");
+}
+
+#[test]
+fn clock() {
+    let mut stack = UndoStack::new();
+    stack.set_clock(Box::new(|| 42));
+    stack.add(vec![Edit::new(0, "", "a")], EditKind::Typing, SelectionSet::default(), SelectionSet::default());
+    assert_eq!(stack.stack[0].time_stamp, 42);
+
+    // The clock survives set_text
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    e.set_undo_clock(Box::new(|| 7));
+    e.set_text("a".to_string());
+    type_text(&mut e, "b");
+    e.undo();
+    assert_eq!(e.get_text(), "a");
+}
+
+#[test]
+fn restore_selections() {
+    // Undoing a cut selects the cut text again
+    let mut e = editor_with_mode("one two three", CodeEditorMode::Rust);
+    e.set_selection((4, 0), (7, 0));
+    e.cut();
+    e.undo();
+    assert_eq!(e.get_selected_text(), "two");
+    e.redo();
+    assert_eq!(e.get_selection(), Selection::cursor(4));
+
+    // Every cursor comes back
+    let mut e = editor_with_mode("a\nb", CodeEditorMode::Rust);
+    e.set_cursor((1, 0));
+    e.add_selection((1, 1), (1, 1));
+    type_text(&mut e, "x");
+    e.set_cursor((0, 0));
+    e.undo();
+    assert_eq!(e.get_selections().iter().map(|s| s.head).collect::<Vec<usize>>(), vec![1, 3]);
+}
+
+#[test]
+fn history() {
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    let time = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(100));
+    let clock = time.clone();
+    e.set_undo_clock(Box::new(move || clock.load(std::sync::atomic::Ordering::Relaxed) as u128));
+
+    type_text(&mut e, "ab");
+    time.store(200, std::sync::atomic::Ordering::Relaxed);
+    e.paste(" c\nd".to_string());
+    e.select_all();
+    e.key_down(None, Some(WidgetKey::Tab));
+
+    let history = e.get_undo_history();
+    let labels : Vec<&str> = history.entries.iter().map(|entry| entry.label.as_str()).collect();
+    assert_eq!(labels, vec!["Typing", "Paste", "Indent"]);
+    assert_eq!(history.entries[1], UndoEntry { label: "Paste".to_string(), time_stamp: 200 });
+    assert_eq!(history.entries[0].time_stamp, 100);
+    assert_eq!(history.position, 3);
+
+    e.jump_to_undo(1);
+    assert_eq!(e.get_text(), "ab");
+    assert_eq!(e.get_undo_history().position, 1);
+
+    e.jump_to_undo(10);
+    assert_eq!(e.get_text(), "    ab c\n    d");
+
+    e.jump_to_undo(0);
+    assert_eq!(e.get_text(), "");
+}
+
+#[test]
+fn persist_history() {
+    let original = "fn main() {}\n";
+    let mut e = editor_with_mode(original, CodeEditorMode::Rust);
+    e.set_cursor((11, 0));
+    type_text(&mut e, " x");
+    e.set_selection((3, 0), (7, 0));
+    e.cut();
+    let edited = e.get_text();
+    let json = e.save_undo_history().unwrap();
+
+    // A new session with the same text continues the history
+    let mut e = editor_with_mode(&edited, CodeEditorMode::Rust);
+    e.load_undo_history(&json).unwrap();
+    assert_eq!(e.get_undo_history().position, 2);
+    e.undo();
+    assert_eq!(e.get_selected_text(), "main");
+    e.undo();
+    assert_eq!(e.get_text(), original);
+    e.redo();
+    e.redo();
+    assert_eq!(e.get_text(), edited);
+
+    // The history only fits the text it was saved for
+    let mut e = editor_with_mode(original, CodeEditorMode::Rust);
+    assert!(e.load_undo_history(&json).is_err());
+    assert!(e.load_undo_history("{}").is_err());
+    assert!(!e.has_undo());
+
+    assert_eq!(TextBuffer::from("").hash(), 0xcbf29ce484222325);
+    assert_ne!(TextBuffer::from("a").hash(), TextBuffer::from("b").hash());
+}
+
+#[test]
+fn persist_size_limit() {
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    for i in 0..10 {
+        e.paste(format!("{}", i));
+    }
+    e.undo();
+
+    let json = e.save_undo_history().unwrap();
+    e.settings.undo_file_size = json.len() - 1;
+    let limited = e.save_undo_history().unwrap();
+    assert!(limited.len() < json.len());
+
+    // The oldest step is left out
+    let text = e.get_text();
+    let mut e = editor_with_mode(&text, CodeEditorMode::Rust);
+    e.load_undo_history(&limited).unwrap();
+    let history = e.get_undo_history();
+    assert_eq!(history.entries.len(), 9);
+    assert_eq!(history.position, 8);
+
+    e.jump_to_undo(0);
+    assert_eq!(e.get_text(), "0");
+    e.jump_to_undo(9);
+    assert_eq!(e.get_text(), "0123456789");
+
+    // Histories above the limit are not loaded
+    e.settings.undo_file_size = 10;
+    assert!(e.load_undo_history(&limited).is_err());
+}
+
+#[test]
+fn persist_many_steps() {
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    for i in 0..1000 {
+        e.paste(format!("{} ", i));
+    }
+    e.jump_to_undo(990);
+
+    // The newest undo steps and the redo steps which fit are kept
+    e.settings.undo_file_size = 10_000;
+    let json = e.save_undo_history().unwrap();
+    assert!(json.len() <= 10_000);
+
+    let text = e.get_text();
+    let mut e = editor_with_mode(&text, CodeEditorMode::Rust);
+    e.settings.undo_file_size = 10_000;
+    e.load_undo_history(&json).unwrap();
+    let history = e.get_undo_history();
+    assert_eq!(history.entries.len(), history.position + 10);
+    assert!(history.position > 0);
+
+    e.jump_to_undo(history.entries.len());
+    assert!(e.get_text().ends_with("998 999 "));
+    e.jump_to_undo(history.position);
+
+    // The limit is exact
+    e.settings.undo_file_size = json.len();
+    assert_eq!(e.save_undo_history().unwrap(), json);
+    e.settings.undo_file_size = json.len() - 1;
+    assert!(e.save_undo_history().unwrap().len() < json.len());
+}
+
+#[test]
+fn undo_tree() {
+    // Without the undo tree a new edit removes the undone steps
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    e.paste("a".to_string());
+    e.paste("b".to_string());
+    e.undo();
+    e.paste("c".to_string());
+    assert!(e.get_undo_branches().is_empty());
+    e.undo();
+    assert!(e.get_undo_branches().is_empty());
+
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    e.settings.undo_tree = true;
+    e.paste("a".to_string());
+    e.paste("b".to_string());
+    e.paste("c".to_string());
+    e.undo();
+    e.undo();
+    e.paste("d".to_string());
+    assert_eq!(e.get_text(), "ad");
+
+    // The undone steps "b" and "c" are a branch next to "d"
+    e.undo();
+    let branches = e.get_undo_branches();
+    assert_eq!(branches.len(), 1);
+    assert_eq!(branches[0].entries.len(), 2);
+    assert_eq!(branches[0].entries[0].label, "Paste");
+
+    assert!(e.switch_undo_branch(0));
+    assert_eq!(e.get_text(), "abc");
+    assert_eq!(e.get_undo_history().entries.len(), 3);
+    assert!(!e.switch_undo_branch(0));
+
+    // Now "d" is the branch
+    e.jump_to_undo(1);
+    assert_eq!(e.get_undo_branches()[0].entries.len(), 1);
+    assert!(e.switch_undo_branch(0));
+    assert_eq!(e.get_text(), "ad");
+    assert!(!e.switch_undo_branch(1));
+}
+
+#[test]
+fn undo_earlier_later() {
+    let mut e = editor_with_mode("", CodeEditorMode::Rust);
+    e.settings.undo_tree = true;
+    e.paste("a".to_string());
+    e.paste("b".to_string());
+    e.undo();
+    e.paste("c".to_string());
+    e.undo();
+    e.paste("d".to_string());
+
+    // The states in the order they were made, across the branches
+    let mut texts = vec![e.get_text()];
+    while e.undo_earlier() {
+        texts.push(e.get_text());
+    }
+    assert_eq!(texts, vec!["ad", "ac", "ab", "a", ""]);
+
+    let mut texts = vec![e.get_text()];
+    while e.undo_later() {
+        texts.push(e.get_text());
+    }
+    assert_eq!(texts, vec!["", "a", "ab", "ac", "ad"]);
+
+    // Undo and redo follow the branch of the state
+    e.undo_earlier();
+    e.undo_earlier();
+    assert_eq!(e.get_text(), "ab");
+    e.undo();
+    assert_eq!(e.get_text(), "a");
+    e.redo();
+    assert_eq!(e.get_text(), "ab");
+    assert_eq!(e.get_undo_branches().len(), 0);
+    e.undo();
+    assert_eq!(e.get_undo_branches().len(), 2);
+}
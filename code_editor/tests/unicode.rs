@@ -0,0 +1,102 @@
+use code_editor::prelude::*;
+
+mod common;
+use common::{ editor, press, type_text };
+
+/// "e" followed by a combining acute accent
+const E_ACUTE : &str = "e\u{301}";
+/// A family of three, emojis joined by zero width joiners
+const FAMILY : &str = "👨\u{200d}👩\u{200d}👧";
+
+#[test]
+fn typing() {
+    let mut e = editor("");
+    type_text(&mut e, "äö 中文 ");
+    type_text(&mut e, E_ACUTE);
+    type_text(&mut e, FAMILY);
+    assert_eq!(e.get_text(), format!("äö 中文 {}{}", E_ACUTE, FAMILY));
+
+    // The combining mark and the joined emojis are a single column each
+    assert_eq!(e.cursor_pos, (8, 0));
+
+    e.set_cursor((3, 0));
+    type_text(&mut e, "日");
+    assert_eq!(e.get_text(), format!("äö 日中文 {}{}", E_ACUTE, FAMILY));
+    assert_eq!(e.cursor_pos, (4, 0));
+}
+
+#[test]
+fn backspace() {
+    let text = format!("a{}b{}c中", E_ACUTE, FAMILY);
+    let mut e = editor(&text);
+    e.set_cursor((6, 0));
+
+    // Every backspace removes a whole grapheme
+    e.key_down(None, Some(WidgetKey::Delete));
+    assert_eq!(e.get_text(), format!("a{}b{}c", E_ACUTE, FAMILY));
+    e.key_down(None, Some(WidgetKey::Delete));
+    e.key_down(None, Some(WidgetKey::Delete));
+    assert_eq!(e.get_text(), format!("a{}b", E_ACUTE));
+    e.key_down(None, Some(WidgetKey::Delete));
+    e.key_down(None, Some(WidgetKey::Delete));
+    assert_eq!(e.get_text(), "a");
+    assert_eq!(e.cursor_pos, (1, 0));
+}
+
+#[test]
+fn movement() {
+    let text = format!("中{}{}x\n{}", E_ACUTE, FAMILY, FAMILY);
+    let mut e = editor(&text);
+    e.set_cursor((0, 0));
+
+    assert_eq!(press(&mut e, WidgetKey::Right), (1, 0));
+    assert_eq!(press(&mut e, WidgetKey::Right), (2, 0));
+    assert_eq!(press(&mut e, WidgetKey::Right), (3, 0));
+    assert_eq!(e.get_selection(), Selection::cursor(1 + 2 + FAMILY.chars().count()));
+    assert_eq!(press(&mut e, WidgetKey::Right), (4, 0));
+    assert_eq!(press(&mut e, WidgetKey::Right), (0, 1));
+    assert_eq!(press(&mut e, WidgetKey::Right), (1, 1));
+
+    assert_eq!(press(&mut e, WidgetKey::Left), (0, 1));
+    assert_eq!(press(&mut e, WidgetKey::Left), (4, 0));
+    assert_eq!(press(&mut e, WidgetKey::Left), (3, 0));
+    assert_eq!(press(&mut e, WidgetKey::Left), (2, 0));
+    assert_eq!(e.get_selection(), Selection::cursor(3));
+
+    // Typing after a grapheme cluster does not split it
+    type_text(&mut e, "-");
+    assert_eq!(e.get_text(), format!("中{}-{}x\n{}", E_ACUTE, FAMILY, FAMILY));
+}
+
+#[test]
+fn selection() {
+    let text = format!("中{}{}x", E_ACUTE, FAMILY);
+    let mut e = editor(&text);
+
+    // Drag from column 1 to column 3, the default advance width is 10 pixels after the line numbers
+    let x = |column: usize| 100 + column * 10 + 1;
+    e.mouse_down((x(1), 1));
+    e.mouse_dragged((x(3), 1));
+    e.mouse_up((x(3), 1));
+    assert_eq!(e.copy(), format!("{}{}", E_ACUTE, FAMILY));
+
+    // Typing replaces the selected graphemes
+    type_text(&mut e, "文");
+    assert_eq!(e.get_text(), "中文x");
+    assert_eq!(e.cursor_pos, (2, 0));
+
+    // Selecting with the keyboard moves by graphemes too
+    let mut e = editor(&text);
+    e.set_cursor((1, 0));
+    e.modifier_changed(true, false, false, false);
+    e.key_down(None, Some(WidgetKey::Right));
+    e.key_down(None, Some(WidgetKey::Right));
+    e.modifier_changed(false, false, false, false);
+    assert_eq!(e.get_selected_text(), format!("{}{}", E_ACUTE, FAMILY));
+
+    // A selection set inside a grapheme snaps to its start
+    let mut e = editor(&text);
+    e.set_selection((1, 0), (3, 0));
+    assert_eq!(e.get_selected_text(), format!("{}{}", E_ACUTE, FAMILY));
+    assert_eq!(TextBuffer::from(text.as_str()).char_to_pos(2), (1, 0));
+}
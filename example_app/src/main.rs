@@ -169,6 +169,26 @@ if result != ANSWER {
                             window.request_redraw();
                         }
                     },
+                    VirtualKeyCode::Home => {
+                        if code_editor.key_down(None, Some(WidgetKey::Home)) {
+                            window.request_redraw();
+                        }
+                    },
+                    VirtualKeyCode::End => {
+                        if code_editor.key_down(None, Some(WidgetKey::End)) {
+                            window.request_redraw();
+                        }
+                    },
+                    VirtualKeyCode::PageUp => {
+                        if code_editor.key_down(None, Some(WidgetKey::PageUp)) {
+                            window.request_redraw();
+                        }
+                    },
+                    VirtualKeyCode::PageDown => {
+                        if code_editor.key_down(None, Some(WidgetKey::PageDown)) {
+                            window.request_redraw();
+                        }
+                    },
                     VirtualKeyCode::Space => {
                         if code_editor.key_down(None, Some(WidgetKey::Space)) {
                             window.request_redraw();